@@ -7,11 +7,32 @@ layout (location = 2) in vec3 in_origin;
 layout (location = 0) out vec4 color;
 
 #define EPSILON 1e-4
-#define light_dir vec3(0.3, 1.0, 0.1)
+#define INFINITY 1e30
+#define CHUNK_SIZE 32
+#define OCTREE_EMPTY 0u
+#define OCTREE_OCCUPIED 0x80000000u
+#define OCCUPANCY_FINE 4
+#define OCCUPANCY_COARSE 16
+#define OCCUPANCY_WORDS 17
+#define SHADOW_SAMPLES 4
+#define AO_OFF 0u
+#define AO_NEIGHBOURS 1u
+#define AO_HEMISPHERE 2u
+#define AO_STRENGTH 0.6
+#define AO_SAMPLES 6
+#define AO_RADIUS 6.0
+#define EMISSION_RANGE 16.0
+#define EMISSION_SAMPLES 4
+#define EMISSION_STRENGTH 4.0
+#define EMISSION_GLOW 1.5
+#define MAX_INTERNAL_REFLECTIONS 4
 
 layout(binding = 1) uniform usampler3D volume;
 layout(binding = 2) uniform Specs {
-  uint size;
+  ivec3 size;
+  uint light_count;
+  ivec3 origin;
+  uint ambient_occlusion;
 } specs;
 
 struct Light {
@@ -25,6 +46,39 @@ layout(std140, binding = 3) readonly buffer LightBuffer {
   Light lights[];
 };
 
+layout(std430, binding = 4) readonly buffer Octree {
+  uint nodes[];
+};
+
+layout(std430, binding = 5) readonly buffer PageTable {
+  uint pages[];
+};
+
+layout(std430, binding = 6) readonly buffer Occupancy {
+  uint occupancy[];
+};
+
+// Defined by Emitters in src/emitters.rs
+layout(std430, binding = 8) readonly buffer EmitterGrid {
+  uint emitter_grid[];
+};
+
+// Defined by AlphaIors in src/misc/alpha_iors.rs, indexed by the alpha of a voxel
+layout(std430, binding = 9) readonly buffer AlphaIors {
+  float iors[];
+};
+
+// Defined by Sun in src/misc/sun.rs
+layout(binding = 7) uniform Sun {
+  vec3 dir;
+  float intensity;
+  vec3 color;
+  float softness;
+  vec3 sky;
+  float ambient;
+} sun;
+
+// The bit layout of a voxel is defined by Voxel in src/voxel.rs
 bool is_empty(uvec4 c) {
   return (c.a >> 4) == 0;
 }
@@ -33,12 +87,21 @@ vec3 get_color(uvec4 c) {
   return vec3(c.r / 255.0, c.g / 255.0, c.b / 255.0);
 }
 
+// Emissive voxels are opaque and keep their intensity in the transparency bits
+bool is_emissive(uvec4 c) {
+  return (c.a & 15) == 15 && !is_empty(c);
+}
+
 float get_transparency(uvec4 c) {
-  return float(c.a >> 4) / 15.0;
+  return is_emissive(c) ? 1.0 : float(c.a >> 4) / 15.0;
 }
 
 float get_reflectivity(uvec4 c) {
-  return float(c.a & 15) / 15.0;
+  return is_emissive(c) ? 0.0 : float(c.a & 15) / 15.0;
+}
+
+float get_emission(uvec4 c) {
+  return is_emissive(c) ? float(c.a >> 4) / 15.0 : 0.0;
 }
 
 vec2 intersect_ray_aabb(in vec3 origin, in vec3 dir, in vec3 aabb_min, in vec3 aabb_max) {
@@ -54,6 +117,107 @@ vec2 intersect_ray_aabb(in vec3 origin, in vec3 dir, in vec3 aabb_min, in vec3 a
   return vec2(near, far);
 }
 
+// Looks up the atlas slot of the chunk containing pos, chunks without a slot only contain air
+uvec4 fetch(in ivec3 pos) {
+  if (any(lessThan(pos, ivec3(0))) || any(greaterThanEqual(pos, specs.size))) {
+    return uvec4(0);
+  }
+
+  const ivec3 chunks = (specs.size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+  const ivec3 chunk = pos / CHUNK_SIZE;
+  const uint page = pages[(chunk.z * chunks.y + chunk.y) * chunks.x + chunk.x];
+
+  if (page == 0) {
+    return uvec4(0);
+  }
+
+  const int slot = int(page) - 1;
+  const ivec3 slots = textureSize(volume, 0) / CHUNK_SIZE;
+  const ivec3 slot_pos = ivec3(slot % slots.x, slot / slots.x % slots.y, slot / (slots.x * slots.y));
+
+  return texelFetch(volume, (slot_pos - chunk) * CHUNK_SIZE + pos, 0);
+}
+
+// Returns the size of the empty cell containing pos and its minimum corner, or 0 if pos lies in
+// an occupied 4x4x4 cell. The octree describes the world down to chunks and the occupancy pyramid
+// the inside of allocated chunks. Positions outside of the octree count as a single empty voxel.
+int empty_node(in ivec3 pos, out ivec3 base) {
+  int size = max(1 << (findMSB(max(max(specs.size.x, specs.size.y), specs.size.z) - 1) + 1), CHUNK_SIZE);
+  base = pos;
+
+  if (any(lessThan(pos, ivec3(0))) || any(greaterThanEqual(pos, ivec3(size)))) {
+    return 1;
+  }
+
+  base = ivec3(0);
+  uint node = nodes[0];
+  while (node != OCTREE_EMPTY && node != OCTREE_OCCUPIED) {
+    size >>= 1;
+    const ivec3 child = ivec3(greaterThanEqual(pos, base + size));
+    base += child * size;
+    node = nodes[node + child.x + child.y * 2 + child.z * 4];
+  }
+
+  if (node == OCTREE_EMPTY) {
+    return size;
+  }
+
+  const ivec3 chunks = (specs.size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+  const ivec3 chunk = pos / CHUNK_SIZE;
+  const int offset = ((chunk.z * chunks.y + chunk.y) * chunks.x + chunk.x) * OCCUPANCY_WORDS;
+  const ivec3 local = pos - chunk * CHUNK_SIZE;
+
+  const ivec3 coarse = local / OCCUPANCY_COARSE;
+  const int coarse_cells = CHUNK_SIZE / OCCUPANCY_COARSE;
+  const int coarse_bit = (coarse.z * coarse_cells + coarse.y) * coarse_cells + coarse.x;
+  if ((occupancy[offset + OCCUPANCY_WORDS - 1] & (1u << coarse_bit)) == 0) {
+    base = chunk * CHUNK_SIZE + coarse * OCCUPANCY_COARSE;
+    return OCCUPANCY_COARSE;
+  }
+
+  const ivec3 fine = local / OCCUPANCY_FINE;
+  const int fine_cells = CHUNK_SIZE / OCCUPANCY_FINE;
+  const int fine_bit = (fine.z * fine_cells + fine.y) * fine_cells + fine.x;
+  if ((occupancy[offset + fine_bit / 32] & (1u << (fine_bit % 32))) == 0) {
+    base = chunk * CHUNK_SIZE + fine * OCCUPANCY_FINE;
+    return OCCUPANCY_FINE;
+  }
+
+  return 0;
+}
+
+// Moves pos to the first voxel after the node at base along the ray and resets the DDA state
+void leave_node(
+    in vec3 origin,
+    in vec3 dir,
+    in ivec3 istep,
+    in ivec3 base,
+    in int size,
+    inout ivec3 pos,
+    out vec3 current,
+    out vec3 normal
+  ) {
+  const vec3 safe_dir = dir + vec3(equal(dir, vec3(0.0))) * EPSILON;
+  const vec3 exits = mix(
+    (vec3(base + max(istep, 0) * size) - origin) / safe_dir,
+    vec3(INFINITY),
+    equal(istep, ivec3(0))
+  );
+
+  int axis = 2;
+  if (exits.x < exits.y && exits.x < exits.z) {
+    axis = 0;
+  } else if (exits.y < exits.z) {
+    axis = 1;
+  }
+
+  pos = clamp(ivec3(floor(origin + exits[axis] * dir)), base, base + size - 1);
+  pos[axis] = istep[axis] > 0 ? base[axis] + size : base[axis] - 1;
+  normal = vec3(0.0);
+  normal[axis] = -istep[axis];
+  current = (vec3(pos + max(istep, 0)) - origin) / safe_dir;
+}
+
 uvec4 intersect_ray(
     in vec3 origin,
     in vec3 dir,
@@ -76,7 +240,7 @@ uvec4 intersect_ray(
 
     vec3 current = (boundary - origin) / (dir + vec3(equal(dir, vec3(0.0))) * EPSILON);
     vec3 normal = vec3(0.0);
-    uvec4 voxel = texelFetch(volume, pos - ivec3(aabb_min), 0);
+    uvec4 voxel = fetch(pos - ivec3(aabb_min));
     uint i = 0;
     bool skip = !is_empty(skip_voxel);
     bool first_skip = false;
@@ -87,10 +251,15 @@ uvec4 intersect_ray(
         all(greaterThanEqual(pos, aabb_min - 1)) &&
         all(lessThanEqual(pos, aabb_max + 1)) &&
         is_empty(voxel) &&
-        i < specs.size * 3
+        i < uint(specs.size.x + specs.size.y + specs.size.z)
       )
     ) {
-      if (current.x < current.y && current.x < current.z) {
+      ivec3 base;
+      const int empty_size = empty_node(pos - ivec3(aabb_min), base);
+
+      if (empty_size > 1) {
+        leave_node(origin, dir, istep, base + ivec3(aabb_min), empty_size, pos, current, normal);
+      } else if (current.x < current.y && current.x < current.z) {
         current.x += delta.x;
         pos.x += istep.x;
         normal = vec3(-istep.x, 0.0, 0.0);
@@ -110,7 +279,7 @@ uvec4 intersect_ray(
         skip = false;
       }
 
-      voxel = texelFetch(volume, pos - ivec3(aabb_min), 0);
+      voxel = fetch(pos - ivec3(aabb_min));
       i += 1;
     }
 
@@ -142,7 +311,8 @@ uvec4 intersect_ray_dest(
   const vec3 boundary = vec3(pos + max(istep, 0.0));
 
   vec3 current = (boundary - origin) / (dir + vec3(equal(dir, vec3(0.0))) * EPSILON);
-  uvec4 voxel = texelFetch(volume, pos - ivec3(aabb_min), 0);
+  vec3 normal;
+  uvec4 voxel = fetch(pos - ivec3(aabb_min));
   uint i = 0;
 
   while (
@@ -150,9 +320,14 @@ uvec4 intersect_ray_dest(
     all(lessThanEqual(pos, aabb_max + 1)) &&
     is_empty(voxel) &&
     all(lessThanEqual(pos * sign(dir), dest * sign(dir))) &&
-    i < specs.size * 3
+    i < uint(specs.size.x + specs.size.y + specs.size.z)
   ) {
-    if (current.x < current.y && current.x < current.z) {
+    ivec3 base;
+    const int empty_size = empty_node(pos - ivec3(aabb_min), base);
+
+    if (empty_size > 1) {
+      leave_node(origin, dir, istep, base + ivec3(aabb_min), empty_size, pos, current, normal);
+    } else if (current.x < current.y && current.x < current.z) {
       current.x += delta.x;
       pos.x += istep.x;
     } else if (current.y < current.z) {
@@ -163,39 +338,289 @@ uvec4 intersect_ray_dest(
       pos.z += istep.z;
     }
     
-    voxel = texelFetch(volume, pos - ivec3(aabb_min), 0);
+    voxel = fetch(pos - ivec3(aabb_min));
     i += 1;
   }
 
+  if (any(greaterThan(pos * sign(dir), dest * sign(dir)))) {
+    return uvec4(0);
+  }
+
   return voxel;
 }
 
+float hash(in vec3 p) {
+  p = fract(p * 0.1031);
+  p += dot(p, p.zyx + 31.32);
+  return fract((p.x + p.y) * p.z);
+}
+
+// How much of the sun reaches pos, averaged over shadow rays spread over a cone of sun.softness
+float sun_visibility(in vec3 pos, in vec3 aabb_min, in vec3 aabb_max) {
+  if (sun.intensity <= EPSILON) {
+    return 0.0;
+  }
+
+  const vec3 tangent = normalize(cross(sun.dir, abs(sun.dir.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0)));
+  const vec3 bitangent = cross(sun.dir, tangent);
+  const int samples = sun.softness > EPSILON ? SHADOW_SAMPLES : 1;
+
+  float visibility = 0.0;
+  for (int i = 0; i < samples; i++) {
+    // Points on a disk, rotated randomly per pixel so the penumbra turns into fine noise
+    const float angle = (float(i) + hash(vec3(gl_FragCoord.xy, i))) * 2.0 * 3.14159265 / samples;
+    const float radius = sun.softness * sqrt((float(i) + 0.5) / samples);
+    const vec3 dir = normalize(sun.dir + (tangent * cos(angle) + bitangent * sin(angle)) * radius);
+
+    vec3 shade_itsct, shade_normal;
+    const uvec4 shade_voxel = intersect_ray(pos, dir, aabb_min, aabb_max, uvec4(0), shade_itsct, shade_normal);
+    visibility += 1.0 - get_transparency(shade_voxel);
+  }
+
+  return visibility / samples;
+}
+
+// How much the voxel at pos blocks, where glass blocks less than opaque voxels
+float occluder(in ivec3 pos, in vec3 aabb_min) {
+  return get_transparency(fetch(pos - ivec3(aabb_min)));
+}
+
+// Occlusion of the corners of the face that was hit by the voxels next to it in front of the face,
+// interpolated over the face. A corner between two occluders is fully occluded, like per-vertex
+// ambient occlusion in meshed voxel worlds.
+float neighbour_occlusion(in vec3 itsct, in vec3 normal, in vec3 aabb_min) {
+  const int axis = normal.x != 0.0 ? 0 : (normal.y != 0.0 ? 1 : 2);
+  ivec3 u = ivec3(0), v = ivec3(0);
+  u[(axis + 1) % 3] = 1;
+  v[(axis + 2) % 3] = 1;
+
+  const ivec3 voxel = ivec3(floor(itsct - normal * 0.5));
+  const ivec3 front = voxel + ivec3(normal);
+  const vec2 f = clamp(vec2(itsct[(axis + 1) % 3] - voxel[(axis + 1) % 3], itsct[(axis + 2) % 3] - voxel[(axis + 2) % 3]), 0.0, 1.0);
+
+  vec4 corners;
+  for (int i = 0; i < 4; i++) {
+    const int du = (i & 1) * 2 - 1;
+    const int dv = (i >> 1) * 2 - 1;
+    const float side1 = occluder(front + du * u, aabb_min);
+    const float side2 = occluder(front + dv * v, aabb_min);
+    const float corner = occluder(front + du * u + dv * v, aabb_min);
+    corners[i] = side1 > 0.5 && side2 > 0.5 ? 1.0 : (side1 + side2 + corner) / 3.0;
+  }
+
+  return mix(mix(corners.x, corners.y, f.x), mix(corners.z, corners.w, f.x), f.y);
+}
+
+// Occlusion by everything within AO_RADIUS, from rays spread over the hemisphere around the normal
+// with a cosine distribution.
+float hemisphere_occlusion(in vec3 itsct, in vec3 normal, in vec3 aabb_min, in vec3 aabb_max) {
+  const vec3 tangent = normalize(cross(normal, abs(normal.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0)));
+  const vec3 bitangent = cross(normal, tangent);
+  const vec3 origin = itsct + normal * 0.01;
+  const float offset = hash(vec3(gl_FragCoord.xy, AO_SAMPLES));
+
+  float occlusion = 0.0;
+  for (int i = 0; i < AO_SAMPLES; i++) {
+    const float r = (float(i) + offset) / AO_SAMPLES;
+    const float angle = (float(i) * 2.39996 + offset * 6.28318);
+    const vec3 dir = normalize(
+      (tangent * cos(angle) + bitangent * sin(angle)) * sqrt(r) + normal * sqrt(1.0 - r)
+    );
+
+    const uvec4 voxel = intersect_ray_dest(origin, dir, aabb_min, aabb_max, origin + dir * AO_RADIUS);
+    occlusion += get_transparency(voxel);
+  }
+
+  return occlusion / AO_SAMPLES;
+}
+
+float ambient_occlusion(in vec3 itsct, in vec3 normal, in vec3 aabb_min, in vec3 aabb_max) {
+  // Rays that start inside a voxel hit it without crossing a face
+  if (specs.ambient_occlusion == AO_OFF || normal == vec3(0.0)) {
+    return 1.0;
+  }
+
+  const float occlusion = specs.ambient_occlusion == AO_NEIGHBOURS
+    ? neighbour_occlusion(itsct, normal, aabb_min)
+    : hemisphere_occlusion(itsct, normal, aabb_min, aabb_max);
+  return 1.0 - AO_STRENGTH * occlusion;
+}
+
+// Next event estimation of the light from emissive voxels near the hit, looked up in the light grid
+// cell of its chunk. Cells with more emitters than EMISSION_SAMPLES are sampled in strata, with
+// every sample standing in for its share of the emitters.
+vec3 emitted_light(in vec3 itsct, in vec3 normal, in vec3 aabb_min, in vec3 aabb_max) {
+  const ivec3 pos = ivec3(floor(itsct - normal * 0.5)) - ivec3(aabb_min);
+  if (any(lessThan(pos, ivec3(0))) || any(greaterThanEqual(pos, specs.size))) {
+    return vec3(0.0);
+  }
+
+  const ivec3 chunks = (specs.size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+  const ivec3 chunk = pos / CHUNK_SIZE;
+  const int cell = ((chunk.z * chunks.y + chunk.y) * chunks.x + chunk.x) * 2;
+  const uint offset = emitter_grid[cell];
+  const uint count = emitter_grid[cell + 1];
+  const uint samples = min(count, uint(EMISSION_SAMPLES));
+  const float weight = float(count) / max(float(samples), 1.0);
+  const float jitter = hash(vec3(gl_FragCoord.xy, EMISSION_SAMPLES));
+  const vec3 origin = itsct + normal * 0.01;
+
+  vec3 light = vec3(0.0);
+  for (uint i = 0; i < samples; i++) {
+    const uint index = samples == count ? i : uint((float(i) + jitter) * weight);
+    const uint packed = emitter_grid[offset + index];
+    const ivec3 emitter = ivec3(packed & 1023, (packed >> 10) & 1023, packed >> 20);
+    const vec3 center = vec3(emitter) + aabb_min + 0.5;
+
+    const vec3 to_emitter = center - origin;
+    const float dist = length(to_emitter);
+    const vec3 dir = to_emitter / dist;
+    // The hit voxel itself lies behind its face
+    const float cosine = normal == vec3(0.0) ? 1.0 : dot(normal, dir);
+    if (dist > EMISSION_RANGE || cosine <= 0.0) {
+      continue;
+    }
+
+    // The emitter is visible if the first voxel along the way is within half a diagonal of its center
+    vec3 shade_itsct, shade_normal;
+    const uvec4 blocker = intersect_ray(origin, dir, aabb_min, aabb_max, uvec4(0), shade_itsct, shade_normal);
+    const float visibility = length(shade_itsct - origin) >= dist - 0.87 ? 1.0 : 1.0 - get_transparency(blocker);
+    if (visibility <= EPSILON) {
+      continue;
+    }
+
+    const uvec4 voxel = fetch(emitter);
+    const float window = pow(1.0 - dist / EMISSION_RANGE, 2.0);
+    light += get_color(voxel) * get_emission(voxel) * EMISSION_STRENGTH * window * cosine * visibility / (1.0 + dist * dist);
+  }
+
+  return light * weight;
+}
+
+// The sky seen along dir, with a glow around the sun
+vec3 sky_color(in vec3 dir) {
+  const float glow = pow(max(dot(dir, sun.dir), 0.0), 256.0);
+  return sun.sky + sun.color * sun.intensity * glow;
+}
+
+// Schlick's approximation of the share of light that is reflected at the border between two media,
+// where cosine is the angle to the normal on the side with the lower index of refraction
+float fresnel(in float cosine, in float n1, in float n2) {
+  const float r0 = pow((n1 - n2) / (n1 + n2), 2.0);
+  return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+// What a reflection off a transparent voxel shows, lit by the sky and the sun without shadows to
+// keep it to a single ray
+vec3 reflected_color(in vec3 origin, in vec3 dir, in vec3 aabb_min, in vec3 aabb_max) {
+  vec3 itsct, normal;
+  const uvec4 voxel = intersect_ray(origin, dir, aabb_min, aabb_max, uvec4(0), itsct, normal);
+  if (is_empty(voxel)) {
+    return sky_color(dir);
+  }
+
+  return get_color(voxel) * (sun.ambient + 0.5 * sun.color * sun.intensity + EMISSION_GLOW * get_emission(voxel));
+}
+
+// Follows a ray that entered the voxel at pos through all voxels equal to medium and bends it by
+// Snell's law where it leaves them, returning the point it leaves at and the index of refraction
+// on the other side. Rays that are totally reflected stay inside for up to
+// MAX_INTERNAL_REFLECTIONS faces and then leave unbent.
+vec3 pass_through(
+    in vec3 origin,
+    inout vec3 dir,
+    in ivec3 pos,
+    in uvec4 medium,
+    in float ior,
+    in vec3 aabb_min,
+    out float next_ior
+  ) {
+  vec3 exit = origin;
+  for (int reflections = 0; reflections <= MAX_INTERNAL_REFLECTIONS; reflections++) {
+    const ivec3 istep = ivec3(sign(dir));
+    const vec3 safe_dir = dir + vec3(equal(dir, vec3(0.0))) * EPSILON;
+    const vec3 delta = abs(1.0 / safe_dir);
+    vec3 current = (vec3(pos + max(istep, 0)) - origin) / safe_dir;
+
+    uvec4 next = medium;
+    int axis = 0;
+    float t = 0.0;
+    for (uint i = 0; next == medium && i < uint(specs.size.x + specs.size.y + specs.size.z); i++) {
+      axis = current.x < current.y && current.x < current.z ? 0 : (current.y < current.z ? 1 : 2);
+      t = current[axis];
+      current[axis] += delta[axis];
+      pos[axis] += istep[axis];
+      next = fetch(pos - ivec3(aabb_min));
+    }
+
+    exit = origin + dir * t;
+    vec3 outward = vec3(0.0);
+    outward[axis] = float(istep[axis]);
+    next_ior = !is_empty(next) && get_transparency(next) < 1.0 - EPSILON ? iors[next.a] : 1.0;
+
+    const vec3 refracted = refract(dir, -outward, ior / next_ior);
+    if (refracted != vec3(0.0) || reflections == MAX_INTERNAL_REFLECTIONS) {
+      dir = refracted != vec3(0.0) ? refracted : dir;
+      break;
+    }
+
+    dir = reflect(dir, outward);
+    pos[axis] -= istep[axis];
+    origin = exit;
+  }
+
+  return exit;
+}
+
 void main() {
-  const vec3 aabb_min = vec3(-int(specs.size / 2));
-  const vec3 aabb_max = vec3(int(specs.size / 2) - 1);
+  const vec3 aabb_min = vec3(specs.origin);
+  const vec3 aabb_max = vec3(specs.origin + specs.size - 1);
   vec3 dir = normalize(raw_dir);
   vec3 origin = in_origin;
 
   vec4 final_color = vec4(0.0);
   uvec4 skip_voxel = uvec4(0);
+  float medium_ior = 1.0;
 
   while (true) {
     vec3 itsct, normal;
     const uvec4 voxel = intersect_ray(origin, dir, aabb_min, aabb_max, skip_voxel, itsct, normal);
     skip_voxel = voxel;
 
-    vec3 shade_itsct, shade_normal;
-    const uvec4 shade_voxel = intersect_ray(itsct, light_dir, aabb_min, aabb_max, uvec4(0), shade_itsct, shade_normal);
-    const float shade = 1.0 - 0.5 * get_transparency(shade_voxel);
+    if (is_empty(voxel)) {
+      final_color += vec4(sky_color(dir), 1.0) * (1.0 - final_color.a);
+      break;
+    }
+
+    const float ao = ambient_occlusion(itsct, normal, aabb_min, aabb_max);
+    const vec3 shade =
+      sun.ambient * ao +
+      0.5 * sun.color * sun.intensity * sun_visibility(itsct, aabb_min, aabb_max) +
+      emitted_light(itsct, normal, aabb_min, aabb_max) +
+      EMISSION_GLOW * get_emission(voxel);
     const float transparency = get_transparency(voxel);
     const float reflectivity = get_reflectivity(voxel);
 
+    // Rays that start inside a transparent voxel have no face to refract at and pass straight through
+    const bool refracts = transparency < 1.0 - EPSILON && normal != vec3(0.0);
+    const float ior = iors[voxel.a];
+    const vec3 refracted = refract(dir, normal, medium_ior / ior);
+    float reflected = reflectivity;
+    if (refracts) {
+      const float cosine = medium_ior <= ior ? dot(-dir, normal) : dot(-refracted, normal);
+      const float share = refracted == vec3(0.0) ? 1.0 : (medium_ior == ior ? 0.0 : fresnel(cosine, medium_ior, ior));
+      reflected = reflectivity + (1.0 - reflectivity) * share;
+
+      const vec3 reflection = reflected_color(itsct + normal * 1e-3, reflect(dir, normal), aabb_min, aabb_max);
+      final_color += vec4(reflection, 1.0) * reflected * (1.0 - final_color.a);
+    }
+
     final_color +=
       vec4(
         get_color(voxel) * shade * transparency, transparency
-      ) * (1.0 - final_color.a) * (1.0 - reflectivity); 
+      ) * (1.0 - final_color.a) * (refracts ? 1.0 : 1.0 - reflectivity); 
 
-    for (int i = 0; i <= lights.length(); i++) {
+    for (uint i = 0; i < specs.light_count; i++) {
       const vec3 dist = lights[i].pos - itsct;
       if (dot(dist, dist) <= lights[i].max_radius * lights[i].max_radius) {
         const uvec4 light_voxel = intersect_ray_dest(itsct, dist, aabb_min, aabb_max, lights[i].pos);
@@ -212,9 +637,18 @@ void main() {
       }
     }
 
-    if (final_color.a >= 1.0 - EPSILON || (transparency >= 1.0 - EPSILON && reflectivity <= EPSILON) || is_empty(voxel)) {
+    if (final_color.a >= 1.0 - EPSILON || (transparency >= 1.0 - EPSILON && reflectivity <= EPSILON)) {
       break;
     }
+
+    if (refracts && reflected < 1.0 - EPSILON) {
+      dir = refracted;
+      const ivec3 pos = ivec3(floor(itsct - normal * 0.5));
+      const vec3 exit = pass_through(itsct, dir, pos, voxel, ior, aabb_min, medium_ior);
+      // Start just inside the medium, which skip_voxel lets the next ray leave
+      origin = exit - dir * 1e-3;
+      continue;
+    }
     
     if (reflectivity > EPSILON) {
       dir = dir - 2.0 * dot(dir, normal) * normal;
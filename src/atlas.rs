@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::math::IVec3;
+use crate::volume::{Chunk, DirtyBox, Volume, CHUNK_SIZE};
+use crate::voxel::Voxel;
+use crate::vulkan::texture::Region;
+
+// Resident chunks are packed into slots of a single 3D texture. The page table has one entry per
+// chunk of the world, which is either 0 for chunks that only contain air or the slot index + 1.
+// Slots are numbered in x, y, z order. Chunks that become empty give their slot back, and when no
+// slot is free the atlas grows along z, which keeps the numbers of the existing slots.
+pub struct Atlas {
+    chunks: IVec3,
+    slots: IVec3,
+    free: Vec<u32>,
+    resident: HashMap<IVec3, u32>,
+    pub pages: Vec<u32>,
+}
+
+impl Atlas {
+    pub fn new(volume: &Volume) -> Self {
+        let chunks = volume.chunks_per_axis();
+        let len = chunks.iter().product::<i32>();
+        let capacity = (volume.chunks().count() as i32 * 2).max(1).min(len);
+
+        // Terrain is mostly wide and shallow, so the atlas follows the shape of the world instead of
+        // being a cube large enough to hold every resident chunk.
+        let side = (capacity as f64).cbrt().ceil() as i32;
+        let x = side.min(chunks.x);
+        let z = side.min(chunks.z);
+        let y = (capacity + x * z - 1) / (x * z);
+        let slots = IVec3::new(x, y, z);
+
+        Self {
+            chunks,
+            slots,
+            free: (0..slots.iter().product::<i32>() as u32).rev().collect(),
+            resident: HashMap::new(),
+            pages: vec![0; len as usize],
+        }
+    }
+
+    // Assigns slots to chunks that are not resident yet and returns the regions of the atlas that
+    // have to be uploaded. New chunks are uploaded whole, resident chunks only within their dirty box.
+    // If the atlas had to grow, its size changes and every resident chunk is returned whole, as the
+    // texture has to be created again.
+    pub fn update<'a>(
+        &mut self,
+        volume: &'a Volume,
+        dirty: &HashMap<IVec3, DirtyBox>,
+    ) -> Vec<Region<'a, Voxel>> {
+        let slots = self.slots;
+        let mut regions = Vec::new();
+
+        for (key, dirty) in dirty {
+            let chunk = match volume.chunk(key) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+
+            let index = ((key.z * self.chunks.y + key.y) * self.chunks.x + key.x) as usize;
+            let empty = chunk.data.iter().all(|voxel| voxel.is_empty());
+            let (slot, (min, max)) = match self.resident.get(key).copied() {
+                Some(slot) if empty => {
+                    self.resident.remove(key);
+                    self.free.push(slot);
+                    self.pages[index] = 0;
+                    continue;
+                }
+                Some(slot) => (slot, (dirty.min, dirty.max)),
+                None if empty => continue,
+                None => {
+                    let slot = self.allocate();
+                    self.resident.insert(*key, slot);
+                    self.pages[index] = slot + 1;
+
+                    let min = key * CHUNK_SIZE as i32;
+                    (slot, (min, min.add_scalar(CHUNK_SIZE as i32 - 1)))
+                }
+            };
+
+            regions.push(self.region(chunk, slot, min, max));
+        }
+
+        if self.slots != slots {
+            regions = self
+                .resident
+                .iter()
+                .map(|(key, &slot)| {
+                    let min = key * CHUNK_SIZE as i32;
+                    let max = min.add_scalar(CHUNK_SIZE as i32 - 1);
+                    self.region(volume.chunk(key).unwrap(), slot, min, max)
+                })
+                .collect();
+        }
+
+        regions
+    }
+
+    fn allocate(&mut self) -> u32 {
+        if let Some(slot) = self.free.pop() {
+            return slot;
+        }
+
+        // There are never more resident chunks than chunks in the world
+        let len = self.pages.len() as i32;
+        let layer = self.slots.x * self.slots.y;
+        let before = self.slots.iter().product::<i32>();
+        self.slots.z = (self.slots.z * 2).min((len + layer - 1) / layer);
+        self.free = (before as u32..self.slots.iter().product::<i32>() as u32)
+            .rev()
+            .collect();
+
+        self.free.pop().expect("Texture atlas holds every chunk")
+    }
+
+    fn region<'a>(&self, chunk: &'a Chunk, slot: u32, min: IVec3, max: IVec3) -> Region<'a, Voxel> {
+        let slot = slot as i32;
+        let slot = IVec3::new(
+            slot % self.slots.x,
+            slot / self.slots.x % self.slots.y,
+            slot / (self.slots.x * self.slots.y),
+        );
+        let extent = max - min;
+        let offset = slot * CHUNK_SIZE as i32 + min.map(|value| value % CHUNK_SIZE as i32);
+        Region {
+            offset: (offset.x as u32, offset.y as u32, offset.z as u32),
+            extent: (
+                extent.x as u32 + 1,
+                extent.y as u32 + 1,
+                extent.z as u32 + 1,
+            ),
+            row_length: CHUNK_SIZE as u32,
+            image_height: CHUNK_SIZE as u32,
+            data: &chunk.data[Chunk::index(min)..],
+        }
+    }
+
+    pub fn size(&self) -> IVec3 {
+        self.slots * CHUNK_SIZE as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill_chunk(volume: &mut Volume, key: IVec3, voxel: Voxel) {
+        let min = key * CHUNK_SIZE as i32;
+        for pos in crate::math::box_positions(min, min.add_scalar(CHUNK_SIZE as i32 - 1)) {
+            volume.set(pos, voxel);
+        }
+    }
+
+    #[test]
+    fn grows_when_full() {
+        let mut volume = Volume::new(IVec3::new(256, 32, 256));
+        fill_chunk(&mut volume, IVec3::zeros(), Voxel::solid(255, 0, 0));
+        let mut atlas = Atlas::new(&volume);
+        let dirty = volume.take_dirty();
+        atlas.update(&volume, &dirty);
+        let size = atlas.size();
+
+        for x in 0..8 {
+            fill_chunk(&mut volume, IVec3::new(x, 0, 1), Voxel::solid(0, 255, 0));
+        }
+        let dirty = volume.take_dirty();
+        let regions = atlas.update(&volume, &dirty);
+
+        assert_ne!(atlas.size(), size);
+        assert_eq!(regions.len(), 9);
+        let mut pages = atlas.pages.iter().filter(|&&page| page != 0).collect::<Vec<_>>();
+        pages.sort();
+        pages.dedup();
+        assert_eq!(pages.len(), 9);
+    }
+
+    #[test]
+    fn frees_empty_chunks() {
+        let mut volume = Volume::new(IVec3::new(64, 32, 64));
+        fill_chunk(&mut volume, IVec3::zeros(), Voxel::solid(255, 0, 0));
+        let mut atlas = Atlas::new(&volume);
+        let dirty = volume.take_dirty();
+        atlas.update(&volume, &dirty);
+        let page = atlas.pages[0];
+
+        fill_chunk(&mut volume, IVec3::zeros(), Voxel::EMPTY);
+        let dirty = volume.take_dirty();
+        assert!(atlas.update(&volume, &dirty).is_empty());
+        assert_eq!(atlas.pages[0], 0);
+
+        fill_chunk(&mut volume, IVec3::new(1, 0, 0), Voxel::solid(0, 255, 0));
+        let dirty = volume.take_dirty();
+        atlas.update(&volume, &dirty);
+        assert_eq!(atlas.pages[1], page);
+    }
+}
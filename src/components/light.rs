@@ -0,0 +1,34 @@
+use nalgebra::{Vector3, Vector4};
+
+use specs::storage::FlaggedStorage;
+use specs::{Component, DenseVecStorage};
+
+// A point light, laid out like Light in the fragment shader. The light is full strength within
+// min_radius and fades out towards max_radius, and the alpha of the color scales its intensity.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Light {
+    pub pos: Vector3<f32>,
+    padding: f32,
+    pub color: Vector4<f32>,
+    pub min_radius: f32,
+    pub max_radius: f32,
+    padding2: [f32; 2],
+}
+
+impl Light {
+    pub fn new(pos: Vector3<f32>, color: Vector4<f32>, min_radius: f32, max_radius: f32) -> Self {
+        Self {
+            pos,
+            color,
+            min_radius,
+            max_radius,
+            ..Default::default()
+        }
+    }
+}
+
+// Flagged, so the light system only uploads the lights again when one was added, changed or removed
+impl Component for Light {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
@@ -0,0 +1,757 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use nalgebra::{Point3, Rotation3, Unit, Vector3};
+
+use crate::components::tree::{self, Grow};
+use crate::volume::Volume;
+
+// Derivation stops early once the string grows beyond this many modules.
+const MAX_MODULES: usize = 100_000;
+// How far along the branches the tree grows every step, in voxels.
+const GROWTH: f32 = 1.0;
+const LEAF_SIZE: f32 = 3.0;
+// Lengths, widths and leaf sizes beyond this many voxels are taken as a mistake in the file rather
+// than drawn.
+const MAX_SIZE: f32 = 256.0;
+
+#[derive(Debug)]
+pub enum LSystemError {
+    Io(io::Error),
+    Invalid(usize, String),
+    MissingAxiom,
+    Derivation(String),
+    OutOfRange(char, f32),
+}
+
+impl fmt::Display for LSystemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LSystemError::Io(error) => write!(f, "{}", error),
+            LSystemError::Invalid(line, message) => write!(f, "Line {}: {}", line, message),
+            LSystemError::MissingAxiom => write!(f, "Missing axiom"),
+            LSystemError::Derivation(message) => write!(f, "Derivation failed: {}", message),
+            LSystemError::OutOfRange(symbol, value) => write!(
+                f,
+                "{}({}) is out of range, sizes are limited to {} voxels",
+                symbol, value, MAX_SIZE
+            ),
+        }
+    }
+}
+
+impl Error for LSystemError {}
+
+impl From<io::Error> for LSystemError {
+    fn from(error: io::Error) -> Self {
+        LSystemError::Io(error)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f32),
+    Param(usize),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // Fails instead of returning infinity or NaN, like after a division by zero.
+    fn eval(&self, params: &[f32]) -> Result<f32, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Param(index) => Ok(params[*index]),
+            Expr::Neg(expr) => Ok(-expr.eval(params)?),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(params)?, right.eval(params)?);
+                let value = match op {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    _ => left / right,
+                };
+
+                if value.is_finite() {
+                    Ok(value)
+                } else {
+                    Err(format!("{} {} {} is not a finite number", left, op, right))
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Condition {
+    op: &'static str,
+    left: Expr,
+    right: Expr,
+}
+
+impl Condition {
+    fn eval(&self, params: &[f32]) -> Result<bool, String> {
+        let (left, right) = (self.left.eval(params)?, self.right.eval(params)?);
+        Ok(match self.op {
+            "<=" => left <= right,
+            ">=" => left >= right,
+            "==" => (left - right).abs() < f32::EPSILON,
+            "<" => left < right,
+            _ => left > right,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    symbol: char,
+    arity: usize,
+    condition: Option<Condition>,
+    successor: Vec<(char, Vec<Expr>)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Module {
+    symbol: char,
+    params: Vec<f32>,
+}
+
+// Recursive descent parser for the arithmetic in module parameters and conditions. Names are
+// either parameters of the predecessor or constants defined earlier in the file.
+struct ExprParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    names: &'a [String],
+    constants: &'a HashMap<String, f32>,
+}
+
+impl ExprParser<'_> {
+    fn parse(
+        text: &str,
+        names: &[String],
+        constants: &HashMap<String, f32>,
+    ) -> Result<Expr, String> {
+        let mut parser = ExprParser {
+            chars: text.chars().filter(|c| !c.is_whitespace()).collect(),
+            pos: 0,
+            names,
+            constants,
+        };
+
+        let expr = parser.expr()?;
+        match parser.peek() {
+            Some(c) => Err(format!("Unexpected '{}' in '{}'", c, text)),
+            None => Ok(expr),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        while let Some(op @ '+') | Some(op @ '-') = self.peek() {
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some(op @ '*') | Some(op @ '/') = self.peek() {
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.factor()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.factor()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                if self.peek() != Some(')') {
+                    return Err("Expected ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .filter(|c| c.is_ascii_digit() || *c == '.')
+                    .is_some()
+                {
+                    self.pos += 1;
+                }
+
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("Invalid number '{}'", number))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .filter(|c| c.is_alphanumeric() || *c == '_')
+                    .is_some()
+                {
+                    self.pos += 1;
+                }
+
+                let name: String = self.chars[start..self.pos].iter().collect();
+                if let Some(index) = self.names.iter().position(|param| *param == name) {
+                    Ok(Expr::Param(index))
+                } else if let Some(value) = self.constants.get(&name) {
+                    Ok(Expr::Number(*value))
+                } else {
+                    Err(format!("Unknown name '{}'", name))
+                }
+            }
+            Some(c) => Err(format!("Unexpected '{}'", c)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+// Splits a string of modules like `F(l * 2) [ +A(l) ]` into symbols and their arguments.
+fn split_modules(text: &str) -> Result<Vec<(char, Vec<&str>)>, String> {
+    let mut modules: Vec<(char, Vec<&str>)> = Vec::new();
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => continue,
+            ')' | ',' => return Err(format!("Unexpected '{}'", c)),
+            '(' => {
+                let module = match modules.last_mut() {
+                    Some(module) if module.1.is_empty() => module,
+                    _ => return Err("Expected a symbol before '('".to_string()),
+                };
+
+                let mut depth = 1;
+                let mut start = i + 1;
+                let mut args = Vec::new();
+                for (j, c) in &mut chars {
+                    match c {
+                        '(' => depth += 1,
+                        ')' if depth == 1 => {
+                            args.push(&text[start..j]);
+                            depth = 0;
+                            break;
+                        }
+                        ')' => depth -= 1,
+                        ',' if depth == 1 => {
+                            args.push(&text[start..j]);
+                            start = j + 1;
+                        }
+                        _ => {}
+                    }
+                }
+
+                if depth != 0 {
+                    return Err("Expected ')'".to_string());
+                }
+                module.1 = args;
+            }
+            c => modules.push((c, Vec::new())),
+        }
+    }
+
+    Ok(modules)
+}
+
+// A parametric L-system read from a text file. The file holds `key = value` lines for the axiom,
+// the number of iterations and the defaults of the turtle, `const name = value` lines and rules of
+// the form `A(x, y) : condition -> successor`, where the condition is optional. The first rule
+// whose symbol, number of parameters and condition match a module replaces it, modules without a
+// matching rule are kept as they are. Lines starting with # are comments.
+//
+// The turtle starts out facing up and understands these symbols, where angles are in degrees:
+// F(l) draws a branch of length l, f(l) moves without drawing, +(a) and -(a) turn left and right,
+// &(a) and ^(a) pitch down and up, \(a) and /(a) roll, | turns around, !(w) sets the branch width,
+// L(s) places leaves of size s and [ and ] push and pop the state of the turtle. Parameters left
+// out are taken from the defaults and all other symbols are ignored.
+#[derive(Clone, Debug)]
+pub struct LSystem {
+    axiom: Vec<Module>,
+    rules: Vec<Rule>,
+    pub iterations: usize,
+    pub angle: f32,
+    pub length: f32,
+    pub width: f32,
+}
+
+impl LSystem {
+    pub fn from_file<P>(path: P) -> Result<Self, LSystemError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut lsystem = Self {
+            axiom: Vec::new(),
+            rules: Vec::new(),
+            iterations: 5,
+            angle: 25.0,
+            length: 2.0,
+            width: 1.0,
+        };
+        let mut constants = HashMap::new();
+
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: String| LSystemError::Invalid(line_number, message);
+            if line.contains("->") {
+                lsystem
+                    .rules
+                    .push(Self::parse_rule(line, &constants).map_err(invalid)?);
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=').map(str::trim);
+            let key = parts.next().unwrap_or_default();
+            let value = parts
+                .next()
+                .ok_or_else(|| invalid("Expected key = value".to_string()))?;
+
+            match key {
+                "axiom" => {
+                    lsystem.axiom = Self::parse_successor(value, &[], &constants)
+                        .and_then(|modules| {
+                            modules
+                                .into_iter()
+                                .map(|(symbol, args)| {
+                                    let params = args
+                                        .iter()
+                                        .map(|arg| arg.eval(&[]))
+                                        .collect::<Result<_, _>>()?;
+                                    Ok(Module { symbol, params })
+                                })
+                                .collect()
+                        })
+                        .map_err(invalid)?;
+                }
+                "iterations" => {
+                    lsystem.iterations = value
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid value '{}'", value)))?;
+                }
+                "angle" => {
+                    lsystem.angle = Self::parse_number(value, &constants).map_err(invalid)?
+                }
+                "length" => {
+                    lsystem.length = Self::parse_number(value, &constants).map_err(invalid)?
+                }
+                "width" => {
+                    lsystem.width = Self::parse_number(value, &constants).map_err(invalid)?
+                }
+                key if key.starts_with("const ") => {
+                    let value = Self::parse_number(value, &constants).map_err(invalid)?;
+                    constants.insert(key["const ".len()..].trim().to_string(), value);
+                }
+                _ => return Err(invalid(format!("Unknown key '{}'", key))),
+            }
+        }
+
+        if lsystem.axiom.is_empty() {
+            return Err(LSystemError::MissingAxiom);
+        }
+
+        Ok(lsystem)
+    }
+
+    fn parse_number(text: &str, constants: &HashMap<String, f32>) -> Result<f32, String> {
+        ExprParser::parse(text, &[], constants)?.eval(&[])
+    }
+
+    fn parse_rule(line: &str, constants: &HashMap<String, f32>) -> Result<Rule, String> {
+        let mut parts = line.splitn(2, "->");
+        let left = parts.next().unwrap_or_default();
+        let successor = parts.next().unwrap_or_default();
+
+        let mut left = left.splitn(2, ':');
+        let predecessor = split_modules(left.next().unwrap_or_default())?;
+        let (symbol, names) = match predecessor.as_slice() {
+            [(symbol, names)] => (*symbol, names),
+            _ => return Err("Expected a single module before '->'".to_string()),
+        };
+        let names: Vec<_> = names.iter().map(|name| name.trim().to_string()).collect();
+
+        let condition = match left.next() {
+            Some(condition) => {
+                let op = ["<=", ">=", "==", "<", ">"]
+                    .iter()
+                    .find(|op| condition.contains(*op))
+                    .ok_or_else(|| format!("Expected a comparison in '{}'", condition.trim()))?;
+
+                let mut sides = condition.splitn(2, *op);
+                Some(Condition {
+                    op,
+                    left: ExprParser::parse(sides.next().unwrap_or_default(), &names, constants)?,
+                    right: ExprParser::parse(sides.next().unwrap_or_default(), &names, constants)?,
+                })
+            }
+            None => None,
+        };
+
+        Ok(Rule {
+            symbol,
+            arity: names.len(),
+            condition,
+            successor: Self::parse_successor(successor, &names, constants)?,
+        })
+    }
+
+    fn parse_successor(
+        text: &str,
+        names: &[String],
+        constants: &HashMap<String, f32>,
+    ) -> Result<Vec<(char, Vec<Expr>)>, String> {
+        split_modules(text)?
+            .into_iter()
+            .map(|(symbol, args)| {
+                let args = args
+                    .iter()
+                    .map(|arg| ExprParser::parse(arg, names, constants))
+                    .collect::<Result<_, _>>()?;
+                Ok((symbol, args))
+            })
+            .collect()
+    }
+
+    fn derive(&self, modules: &[Module]) -> Result<Vec<Module>, String> {
+        let mut next = Vec::with_capacity(modules.len());
+        for module in modules {
+            let mut rule = None;
+            for candidate in &self.rules {
+                if candidate.symbol != module.symbol || candidate.arity != module.params.len() {
+                    continue;
+                }
+
+                let applies = match &candidate.condition {
+                    Some(condition) => condition.eval(&module.params)?,
+                    None => true,
+                };
+                if applies {
+                    rule = Some(candidate);
+                    break;
+                }
+            }
+
+            match rule {
+                Some(rule) => {
+                    for (symbol, args) in &rule.successor {
+                        let params = args
+                            .iter()
+                            .map(|arg| arg.eval(&module.params))
+                            .collect::<Result<_, _>>()?;
+                        next.push(Module { symbol: *symbol, params });
+                    }
+                }
+                None => next.push(module.clone()),
+            }
+        }
+
+        Ok(next)
+    }
+
+    fn generate(&self) -> Result<Vec<Module>, LSystemError> {
+        let mut modules = self.axiom.clone();
+        for _ in 0..self.iterations {
+            if modules.len() > MAX_MODULES {
+                break;
+            }
+
+            modules = self.derive(&modules).map_err(LSystemError::Derivation)?;
+        }
+
+        Ok(modules)
+    }
+}
+
+// A step of a branch from pos to pos + dir or a cluster of leaves, with the distance along the
+// branches from the root.
+struct Part {
+    pos: Point3<f32>,
+    dir: Vector3<f32>,
+    size: f32,
+    distance: f32,
+    leaf: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Turtle {
+    pos: Point3<f32>,
+    heading: Vector3<f32>,
+    left: Vector3<f32>,
+    up: Vector3<f32>,
+    width: f32,
+    distance: f32,
+}
+
+impl Turtle {
+    fn rotate(&mut self, axis: Vector3<f32>, degrees: f32) {
+        let rotation = Rotation3::from_axis_angle(&Unit::new_normalize(axis), degrees / 180.0 * PI);
+        self.heading = rotation * self.heading;
+        self.left = rotation * self.left;
+        self.up = rotation * self.up;
+    }
+}
+
+fn check_size(symbol: char, value: f32) -> Result<f32, LSystemError> {
+    if value.abs() <= MAX_SIZE {
+        Ok(value)
+    } else {
+        Err(LSystemError::OutOfRange(symbol, value))
+    }
+}
+
+// A tree drawn by a turtle following the string of an L-system. The whole string is interpreted
+// up front and every step draws the parts of the tree that are one step further from the root, so
+// all branches grow at the same time.
+pub struct LSystemTree {
+    parts: Vec<Part>,
+    drawn: usize,
+    distance: f32,
+}
+
+impl LSystemTree {
+    pub fn new(lsystem: &LSystem, start: Point3<f32>) -> Result<Self, LSystemError> {
+        check_size('!', lsystem.width)?;
+
+        let mut turtle = Turtle {
+            pos: start,
+            heading: Vector3::y(),
+            left: -Vector3::x(),
+            up: Vector3::z(),
+            width: lsystem.width,
+            distance: 0.0,
+        };
+        let mut stack = Vec::new();
+        let mut parts = Vec::new();
+
+        for module in lsystem.generate()? {
+            let param = |default: f32| module.params.first().copied().unwrap_or(default);
+            let size = |default: f32| check_size(module.symbol, param(default));
+            match module.symbol {
+                'F' | 'f' => {
+                    let length = size(lsystem.length)?;
+                    if module.symbol == 'F' {
+                        let steps = length.ceil().max(1.0);
+                        for i in 0..steps as usize {
+                            let t = i as f32 / steps * length;
+                            parts.push(Part {
+                                pos: turtle.pos + turtle.heading * t,
+                                dir: turtle.heading * length / steps,
+                                size: turtle.width,
+                                distance: turtle.distance + t,
+                                leaf: false,
+                            });
+                        }
+                    }
+
+                    turtle.pos += turtle.heading * length;
+                    turtle.distance += length;
+                }
+                '+' => turtle.rotate(turtle.up, param(lsystem.angle)),
+                '-' => turtle.rotate(turtle.up, -param(lsystem.angle)),
+                '&' => turtle.rotate(turtle.left, param(lsystem.angle)),
+                '^' => turtle.rotate(turtle.left, -param(lsystem.angle)),
+                '\\' => turtle.rotate(turtle.heading, param(lsystem.angle)),
+                '/' => turtle.rotate(turtle.heading, -param(lsystem.angle)),
+                '|' => turtle.rotate(turtle.up, 180.0),
+                '!' => turtle.width = size(lsystem.width)?,
+                '[' => stack.push(turtle),
+                ']' => turtle = stack.pop().unwrap_or(turtle),
+                'L' => parts.push(Part {
+                    pos: turtle.pos,
+                    dir: turtle.heading,
+                    size: size(LEAF_SIZE)?,
+                    distance: turtle.distance,
+                    leaf: true,
+                }),
+                _ => {}
+            }
+        }
+
+        // Leaves come after the branch they sit on
+        parts.sort_by(|a, b| {
+            (a.distance, a.leaf)
+                .partial_cmp(&(b.distance, b.leaf))
+                .unwrap_or(Ordering::Equal)
+        });
+
+        Ok(Self {
+            parts,
+            drawn: 0,
+            distance: 0.0,
+        })
+    }
+}
+
+impl Grow for LSystemTree {
+    fn grow(&mut self, volume: &mut Volume) {
+        self.distance += GROWTH;
+
+        let distance = self.distance;
+        let parts = self.parts[self.drawn..]
+            .iter()
+            .take_while(|part| part.distance < distance);
+        let count = parts.clone().count();
+        for part in parts {
+            if part.leaf {
+                let pos = tree::voxel_pos(part.pos);
+                tree::create_leaf(pos, part.size.round() as i32, tree::LEAF_COLOR, volume);
+            } else {
+                let (end, radius) = (part.pos + part.dir, part.size / 2.0);
+                tree::create_capsule(part.pos, end, radius, radius, tree::BRANCH_COLOR, volume);
+            }
+        }
+
+        self.drawn += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::IVec3;
+
+    fn symbols(modules: &[Module]) -> String {
+        modules.iter().map(|module| module.symbol).collect()
+    }
+
+    fn count(modules: &[Module], symbol: char) -> usize {
+        modules.iter().filter(|module| module.symbol == symbol).count()
+    }
+
+    fn parse(name: &str, text: &str) -> Result<LSystem, LSystemError> {
+        let path =
+            std::env::temp_dir().join(format!("voxel-{}-{}.lsystem", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let lsystem = LSystem::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        lsystem
+    }
+
+    #[test]
+    fn parse_conifer() {
+        let lsystem = LSystem::from_file("assets/trees/conifer.lsystem").unwrap();
+        assert_eq!(lsystem.iterations, 13);
+        assert_eq!((lsystem.angle, lsystem.length, lsystem.width), (35.0, 2.0, 1.0));
+        assert_eq!(symbols(&lsystem.axiom), "A");
+        assert_eq!(lsystem.axiom[0].params, vec![3.0, 4.0]);
+        assert_eq!(lsystem.rules.len(), 5);
+    }
+
+    #[test]
+    fn derive_conifer() {
+        let lsystem = LSystem::from_file("assets/trees/conifer.lsystem").unwrap();
+        let modules = lsystem.derive(&lsystem.axiom).unwrap();
+        assert_eq!(symbols(&modules), "!F[&B]/[&B]/[&B]/[&B]/A");
+
+        // The constants are substituted and the apex shrinks
+        assert_eq!(modules[3].params, vec![80.0]);
+        let apex = modules.last().unwrap();
+        assert!((apex.params[0] - 2.7).abs() < 1e-6);
+        assert_eq!(apex.params[1], 3.75);
+
+        // Once the trunk is thin enough the apex ends in leaves
+        let mut modules = lsystem.axiom.clone();
+        for _ in 0..12 {
+            modules = lsystem.derive(&modules).unwrap();
+        }
+        assert_eq!(count(&modules, 'A'), 1);
+        assert_eq!(count(&lsystem.derive(&modules).unwrap(), 'A'), 0);
+    }
+
+    #[test]
+    fn parse_palm() {
+        let lsystem = LSystem::from_file("assets/trees/palm.lsystem").unwrap();
+        assert_eq!(lsystem.iterations, 24);
+        assert_eq!((lsystem.angle, lsystem.length, lsystem.width), (3.0, 2.0, 3.0));
+        assert_eq!(symbols(&lsystem.axiom), "!T");
+        assert_eq!(lsystem.rules.len(), 4);
+    }
+
+    #[test]
+    fn derive_palm() {
+        let lsystem = LSystem::from_file("assets/trees/palm.lsystem").unwrap();
+        let modules = lsystem.derive(&lsystem.axiom).unwrap();
+        assert_eq!(symbols(&modules), "!F&T");
+        assert_eq!(modules[3].params, vec![13.0]);
+
+        // A trunk of 14 segments and six fronds of 7 segments with leaves on each
+        let modules = lsystem.generate().unwrap();
+        assert_eq!(count(&modules, 'F'), 14 + 6 * 7);
+        assert_eq!(count(&modules, 'L'), 6 * 7);
+        assert_eq!(count(&modules, 'T'), 0);
+        assert_eq!(count(&modules, 'D'), 6);
+    }
+
+    #[test]
+    fn grows_step_by_step() {
+        let lsystem = parse("grow", "axiom = F(4) L(3)").unwrap();
+        let mut tree = LSystemTree::new(&lsystem, Point3::new(8.0, 0.0, 8.0)).unwrap();
+        let mut volume = Volume::new(IVec3::repeat(16));
+        let trunk = |y| IVec3::new(8, y, 8);
+
+        // Every step draws one more voxel of the trunk
+        for step in 1..=4 {
+            tree.grow(&mut volume);
+            assert_eq!(volume.get(trunk(step)), tree::BRANCH_COLOR, "step {}", step);
+            assert!(volume.get(trunk(step + 1)).is_empty(), "step {}", step);
+            assert!(volume.get(IVec3::new(9, 4, 8)).is_empty(), "step {}", step);
+        }
+
+        // The leaves at the top of the trunk come last
+        tree.grow(&mut volume);
+        for pos in &[trunk(4), trunk(5), IVec3::new(9, 4, 8), IVec3::new(8, 4, 7)] {
+            assert_eq!(volume.get(*pos), tree::LEAF_COLOR, "at {:?}", pos);
+        }
+        assert_eq!(volume.get(trunk(2)), tree::BRANCH_COLOR);
+    }
+
+    #[test]
+    fn rejects_division_by_zero() {
+        let lsystem = parse("division", "axiom = A(2)\nA(w) -> F(1 / (w - 2))").unwrap();
+        assert!(matches!(lsystem.generate(), Err(LSystemError::Derivation(_))));
+        assert!(matches!(
+            parse("constant", "const w = 1 / 0\naxiom = F"),
+            Err(LSystemError::Invalid(1, _))
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_modules() {
+        let start = Point3::origin();
+        for text in &["axiom = F(1000000000)", "axiom = L(300)", "axiom = !(-300) F"] {
+            let lsystem = parse("oversized", text).unwrap();
+            assert!(matches!(
+                LSystemTree::new(&lsystem, start),
+                Err(LSystemError::OutOfRange(..))
+            ));
+        }
+    }
+}
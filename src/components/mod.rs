@@ -1 +1,3 @@
+pub mod light;
+pub mod lsystem;
 pub mod tree;
\ No newline at end of file
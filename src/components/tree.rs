@@ -1,43 +1,290 @@
 use nalgebra::{distance, Point3, Vector3};
 
+use rand::Rng;
+
 use specs::{Component, DenseVecStorage};
 
-const MIN_DIST: f32 = 2.5;
-const MAX_DIST: f32 = 15.0;
-const BRANCH_COLOR: u32 = 0b11110000_00011001_00111010_01010011;
-const LEAF_COLOR: u32 = 0b11110000_00101001_10100101_01001010;
-const LEAF_SIZE: usize = 5;
+use crate::math::kdtree::KdTree;
+use crate::math::{self, IVec3};
+use crate::volume::Volume;
+use crate::voxel::Voxel;
+
+pub const BRANCH_COLOR: Voxel = Voxel::solid(0b01010011, 0b00111010, 0b00011001);
+pub const LEAF_COLOR: Voxel = Voxel::solid(0b01001010, 0b10100101, 0b00101001);
+
+// Thin branches are drawn a bit thicker than a single voxel, so they stay connected at any angle.
+const MIN_RADIUS: f32 = 0.7;
+// Radii are rounded to powers of this, so a branch is only redrawn once it got a tenth thicker.
+const RADIUS_STEP: f32 = 1.1;
+
+// The shape the attraction points of a tree are scattered in, around the centre of the crown. Cones
+// have their base at the bottom and cones and cylinders are centred halfway up their height.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Envelope {
+    Sphere { radius: f32 },
+    Cone { radius: f32, height: f32 },
+    Ellipsoid { radii: Vector3<f32> },
+    Cylinder { radius: f32, height: f32 },
+}
+
+impl Envelope {
+    // A random point inside of the envelope, relative to its centre. Everything but spheres is
+    // sampled uniformly by rejection from the bounding box.
+    fn sample<R: Rng>(&self, rng: &mut R) -> Vector3<f32> {
+        if let Envelope::Sphere { radius } = *self {
+            let dir = Vector3::new(
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>() * 2.0 - 1.0,
+            );
+            let dir = dir / dir.dot(&dir).sqrt();
+            let len = rng.gen::<f32>();
+            return dir * radius * len;
+        }
+
+        let extent = match *self {
+            Envelope::Sphere { radius } => Vector3::repeat(radius),
+            Envelope::Cone { radius, height } | Envelope::Cylinder { radius, height } => {
+                Vector3::new(radius, height / 2.0, radius)
+            }
+            Envelope::Ellipsoid { radii } => radii,
+        };
+
+        loop {
+            let pos = Vector3::new(
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>() * 2.0 - 1.0,
+                rng.gen::<f32>() * 2.0 - 1.0,
+            )
+            .component_mul(&extent);
+
+            if self.contains(pos) {
+                return pos;
+            }
+        }
+    }
+
+    pub fn scaled(self, factor: f32) -> Self {
+        match self {
+            Envelope::Sphere { radius } => Envelope::Sphere {
+                radius: radius * factor,
+            },
+            Envelope::Cone { radius, height } => Envelope::Cone {
+                radius: radius * factor,
+                height: height * factor,
+            },
+            Envelope::Ellipsoid { radii } => Envelope::Ellipsoid {
+                radii: radii * factor,
+            },
+            Envelope::Cylinder { radius, height } => Envelope::Cylinder {
+                radius: radius * factor,
+                height: height * factor,
+            },
+        }
+    }
+
+    fn contains(&self, pos: Vector3<f32>) -> bool {
+        let horizontal = pos.xz().norm();
+        match *self {
+            Envelope::Sphere { radius } => pos.norm() <= radius,
+            Envelope::Cone { radius, height } => horizontal <= radius * (0.5 - pos.y / height),
+            Envelope::Ellipsoid { radii } => pos.component_div(&radii).norm_squared() <= 1.0,
+            Envelope::Cylinder { radius, height } => {
+                horizontal <= radius && pos.y.abs() <= height / 2.0
+            }
+        }
+    }
+}
+
+// Everything that shapes a tree grown with space colonization. The centre of the crown is placed
+// between height and height + height_variation above the start of the trunk and up to offset to
+// the side. The trunk grows straight up until it is within max_dist of an attraction point, after
+// which branches grow towards the attraction points and remove those within min_dist. After steps
+// steps leaves are placed on the tips. Branches follow the pipe model: tips have tip_radius and
+// where branches fork, the radius raised to pipe_exponent is the sum of that of the forks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeParams {
+    pub envelope: Envelope,
+    pub attractors: usize,
+    pub height: f32,
+    pub height_variation: f32,
+    pub offset: f32,
+    pub min_dist: f32,
+    pub max_dist: f32,
+    pub steps: usize,
+    pub tip_radius: f32,
+    pub pipe_exponent: f32,
+    pub leaf_size: i32,
+    pub branch_color: Voxel,
+    pub leaf_color: Voxel,
+}
+
+impl TreeParams {
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "oak" => Some(Self::oak()),
+            "bush" => Some(Self::bush()),
+            "birch" => Some(Self::birch()),
+            "pine" => Some(Self::pine()),
+            "poplar" => Some(Self::poplar()),
+            _ => None,
+        }
+    }
+
+    // The same kind of tree with a crown that is factor times as large and as high up.
+    pub fn scaled(&self, factor: f32) -> Self {
+        Self {
+            envelope: self.envelope.scaled(factor),
+            height: self.height * factor,
+            height_variation: self.height_variation * factor,
+            offset: self.offset * factor,
+            ..self.clone()
+        }
+    }
 
+    pub fn oak() -> Self {
+        Self {
+            envelope: Envelope::Sphere { radius: 30.0 },
+            attractors: 400,
+            height: 35.0,
+            height_variation: 10.0,
+            offset: 5.0,
+            min_dist: 2.5,
+            max_dist: 15.0,
+            steps: 50,
+            tip_radius: 0.5,
+            pipe_exponent: 2.5,
+            leaf_size: 5,
+            branch_color: BRANCH_COLOR,
+            leaf_color: LEAF_COLOR,
+        }
+    }
+
+    pub fn bush() -> Self {
+        Self {
+            envelope: Envelope::Ellipsoid {
+                radii: Vector3::new(12.0, 6.0, 12.0),
+            },
+            attractors: 200,
+            height: 7.0,
+            height_variation: 2.0,
+            offset: 1.0,
+            min_dist: 2.0,
+            max_dist: 8.0,
+            steps: 25,
+            tip_radius: 0.4,
+            pipe_exponent: 3.0,
+            leaf_size: 5,
+            branch_color: BRANCH_COLOR,
+            leaf_color: Voxel::solid(0b00110100, 0b01111000, 0b00100100),
+        }
+    }
+
+    pub fn birch() -> Self {
+        Self {
+            envelope: Envelope::Ellipsoid {
+                radii: Vector3::new(9.0, 18.0, 9.0),
+            },
+            attractors: 300,
+            height: 32.0,
+            height_variation: 6.0,
+            offset: 2.0,
+            min_dist: 2.0,
+            max_dist: 12.0,
+            steps: 50,
+            tip_radius: 0.4,
+            pipe_exponent: 3.0,
+            leaf_size: 5,
+            branch_color: Voxel::solid(0b11011000, 0b11010100, 0b11001000),
+            leaf_color: Voxel::solid(0b01101010, 0b10110000, 0b00111100),
+        }
+    }
+
+    pub fn pine() -> Self {
+        Self {
+            envelope: Envelope::Cone {
+                radius: 11.0,
+                height: 32.0,
+            },
+            attractors: 300,
+            height: 26.0,
+            height_variation: 6.0,
+            offset: 1.0,
+            min_dist: 2.0,
+            max_dist: 10.0,
+            steps: 50,
+            tip_radius: 0.4,
+            pipe_exponent: 2.5,
+            leaf_size: 3,
+            branch_color: BRANCH_COLOR,
+            leaf_color: Voxel::solid(0b00100110, 0b01011000, 0b00110000),
+        }
+    }
+
+    pub fn poplar() -> Self {
+        Self {
+            envelope: Envelope::Cylinder {
+                radius: 5.0,
+                height: 28.0,
+            },
+            attractors: 250,
+            height: 26.0,
+            height_variation: 6.0,
+            offset: 1.0,
+            min_dist: 2.0,
+            max_dist: 10.0,
+            steps: 50,
+            tip_radius: 0.4,
+            pipe_exponent: 3.0,
+            leaf_size: 4,
+            branch_color: BRANCH_COLOR,
+            leaf_color: Voxel::solid(0b01011000, 0b10011000, 0b00110000),
+        }
+    }
+}
+
+impl Default for TreeParams {
+    fn default() -> Self {
+        Self::oak()
+    }
+}
+
+// A branch ends at pos and starts one voxel back along dir, where its parent ends. Tips is the
+// number of tips growing from it, which is what its radius follows.
 struct Branch {
     pos: Point3<f32>,
     dir: Vector3<f32>,
     next_dirs: Vec<Vector3<f32>>,
     leaf: bool,
+    parent: Option<usize>,
+    tips: usize,
 }
 
 impl Branch {
-    fn new(from: Point3<f32>, dir: Vector3<f32>) -> Self {
+    fn new(from: Point3<f32>, dir: Vector3<f32>, parent: Option<usize>) -> Self {
         Self {
             pos: from + dir,
             dir,
             next_dirs: Vec::new(),
             leaf: true,
+            parent,
+            tips: 1,
         }
     }
 
-    fn next(&mut self) -> Self {
+    fn next(&mut self, index: usize) -> Self {
         self.leaf = false;
         let len = self.next_dirs.len() as f32 + 1.0;
         let next_dir = self.next_dirs.drain(..).fold(self.dir, |acc, v| acc + v) / len;
-        Self::new(self.pos, next_dir.normalize())
+        Self::new(self.pos, next_dir.normalize(), Some(index))
     }
 
-    fn next_option(&mut self) -> Option<Self> {
+    fn next_option(&mut self, index: usize) -> Option<Self> {
         if self.next_dirs.is_empty() {
             return None;
         }
 
-        Some(self.next())
+        Some(self.next(index))
     }
 
     fn add(&mut self, dir: Vector3<f32>) {
@@ -45,224 +292,369 @@ impl Branch {
     }
 }
 
+// An attraction point together with the branch closest to it. Branches are only ever added, so
+// only the branches added since the last step have to be checked to keep it up to date.
+struct Attractor {
+    pos: Point3<f32>,
+    branch: usize,
+    dist: f32,
+}
+
+// Anything the tree system can grow into the volume, one step at a time.
+pub trait Grow: Send + Sync {
+    fn grow(&mut self, volume: &mut Volume);
+}
+
 #[derive(Component)]
-pub struct Tree {
-    leaves: Vec<Point3<f32>>,
+pub struct Tree(Box<dyn Grow>);
+
+impl Tree {
+    pub fn new<T: Grow + 'static>(generator: T) -> Self {
+        Self(Box::new(generator))
+    }
+
+    pub fn grow(&mut self, volume: &mut Volume) {
+        self.0.grow(volume);
+    }
+}
+
+pub fn voxel_pos(pos: Point3<f32>) -> IVec3 {
+    pos.coords.map(|value| value.round() as i32)
+}
+
+// Fills every voxel whose centre is inside of a capsule from `from` to `to`, with a radius that
+// tapers from from_radius to to_radius.
+pub fn create_capsule(
+    from: Point3<f32>,
+    to: Point3<f32>,
+    from_radius: f32,
+    to_radius: f32,
+    color: Voxel,
+    volume: &mut Volume,
+) {
+    let from_radius = from_radius.max(MIN_RADIUS);
+    let to_radius = to_radius.max(MIN_RADIUS);
+    let extent = IVec3::repeat(from_radius.max(to_radius).ceil() as i32);
+    let min = voxel_pos(from.inf(&to)) - extent;
+    let max = voxel_pos(from.sup(&to)) + extent;
+
+    let axis = to - from;
+    let length = axis.norm_squared();
+    for pos in math::box_positions(min, max) {
+        let point = Point3::from(pos.cast::<f32>());
+        // How far along the axis the closest point on it is, from 0 at `from` to 1 at `to`
+        let t = if length > 0.0 {
+            ((point - from).dot(&axis) / length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let radius = from_radius + (to_radius - from_radius) * t;
+        if distance(&point, &(from + axis * t)) <= radius {
+            volume.set(pos, color);
+        }
+    }
+}
+
+// A cube of leaves exactly size voxels wide, with its edges left out once it is at least 3 wide.
+// Even sizes extend one voxel further towards positive coordinates.
+pub fn create_leaf(pos: IVec3, size: i32, color: Voxel, volume: &mut Volume) {
+    let min = -(size - 1) / 2;
+    let max = size / 2;
+    for offset in math::box_positions(IVec3::repeat(min), IVec3::repeat(max)) {
+        let c = offset.iter().filter(|value| **value == min || **value == max).count();
+        if size >= 3 && c >= 2 {
+            continue;
+        }
+
+        volume.set(pos + offset, color);
+    }
+}
+
+// Grows branches towards a cloud of attraction points using space colonization.
+pub struct SpaceColonization {
+    params: TreeParams,
+    leaves: Vec<Attractor>,
     branches: Vec<Branch>,
-    width: f32,
+    recent_branches: KdTree,
+    step: usize,
     done: bool,
 }
 
-impl Tree {
-    pub fn new(
-        center: Point3<f32>,
-        radius: f32,
-        leaves: usize,
+impl SpaceColonization {
+    pub fn new<R: Rng>(
         start: Point3<f32>,
-        data: &mut Vec<u32>,
-        size: usize,
+        params: &TreeParams,
+        volume: &mut Volume,
+        rng: &mut R,
     ) -> Self {
-        let mut leaves = Vec::with_capacity(leaves);
-        for _ in 0..leaves.capacity() {
-            let dir = Vector3::new(
-                rand::random::<f32>() * 2.0 - 1.0,
-                rand::random::<f32>() * 2.0 - 1.0,
-                rand::random::<f32>() * 2.0 - 1.0,
+        let center = start
+            + Vector3::new(
+                rng.gen::<f32>() * 2.0 * params.offset - params.offset,
+                params.height + rng.gen::<f32>() * params.height_variation,
+                rng.gen::<f32>() * 2.0 * params.offset - params.offset,
             );
-            let dir = dir / dir.dot(&dir).sqrt();
-            let len = rand::random::<f32>();
-            let pos = center + dir * radius * len;
 
-            leaves.push(pos);
-        }
+        let leaves = (0..params.attractors)
+            .map(|_| center + params.envelope.sample(rng))
+            .collect::<Vec<_>>();
 
-        let mut branches = vec![Branch::new(start, Vector3::new(0.0, 1.0, 0.0))];
+        let mut branches = vec![Branch::new(start, Vector3::new(0.0, 1.0, 0.0), None)];
         let mut done = false;
         while !done {
             let branch = branches.last().unwrap();
             for leaf in &leaves {
                 let dist = distance(&branch.pos, leaf);
-                if dist < MAX_DIST {
+                if dist < params.max_dist {
                     done = true;
                     break;
                 }
             }
 
-            let branch = branches.last_mut().unwrap().next();
+            let index = branches.len() - 1;
+            let branch = branches[index].next(index);
             branches.push(branch);
         }
 
-        for branch in &branches {
-            Self::create_branch_thickness(branch.pos, branch.dir, 6, data, size);
+        let mut recent_branches = KdTree::new();
+        for (i, branch) in branches.iter().enumerate() {
+            recent_branches.insert(branch.pos, i);
         }
 
-        Tree {
+        let leaves = leaves
+            .into_iter()
+            .map(|pos| Attractor {
+                pos,
+                branch: 0,
+                dist: f32::INFINITY,
+            })
+            .collect();
+
+        let tree = Self {
+            params: params.clone(),
             leaves,
             branches,
-            width: 5.0,
+            recent_branches,
+            step: 0,
             done: false,
+        };
+
+        for i in 0..tree.branches.len() {
+            tree.create_branch(i, volume);
         }
-    }
 
-    fn create_branch(pos: Point3<f32>, data: &mut Vec<u32>, size: usize) {
-        data[pos.z.round() as usize * size * size
-            + pos.y.round() as usize * size
-            + pos.x.round() as usize] = BRANCH_COLOR;
+        tree
     }
 
-    fn create_branch_thickness(
-        pos: Point3<f32>,
-        dir: Vector3<f32>,
-        thickness: usize,
-        data: &mut Vec<u32>,
-        size: usize,
-    ) {
-        for i in 0..thickness + 1 - (thickness & 1) {
-            for j in 0..thickness + 1 - (thickness & 1) {
-                if (thickness & 1) == 0 {
-                    let c = (i == 0) as usize
-                        + (j == 0) as usize
-                        + (i == thickness - (thickness & 1)) as usize
-                        + (j == thickness - (thickness & 1)) as usize;
-
-                    if c >= 2 {
-                        continue;
-                    }
-                }
-
-                let start = (thickness as f32 / 2.0).floor();
-                let mut offset = Vector3::zeros();
-                if dir.x.abs() < dir.y.abs() || dir.x.abs() < dir.z.abs() {
-                    if dir.y.abs() < dir.z.abs() {
-                        offset += Vector3::x() * (i as f32 - start);
-                        offset += Vector3::y() * (j as f32 - start);
-                    } else {
-                        offset += Vector3::x() * (i as f32 - start);
-                        offset += Vector3::z() * (j as f32 - start);
-                    }
-                } else {
-                    offset += Vector3::y() * (i as f32 - start);
-                    offset += Vector3::z() * (j as f32 - start);
-                }
-
-                Self::create_branch(pos + offset, data, size);
-            }
-        }
+    fn radius(&self, tips: usize) -> f32 {
+        let radius = self.params.tip_radius * (tips as f32).powf(1.0 / self.params.pipe_exponent);
+        RADIUS_STEP.powf(radius.log(RADIUS_STEP).round())
     }
 
-    fn check_weight(data: &mut Vec<u32>, size: usize, x: usize, y: usize, z: usize) -> usize {
-        let mut weight = 0;
-
-        for i in -1..=1 {
-            for j in -1..=1 {
-                for k in -1..=1 {
-                    let x = x as i32 + i;
-                    let y = y as i32 + j;
-                    let z = z as i32 + k;
-                    if x < 0
-                        || y < 0
-                        || z < 0
-                        || x >= size as i32
-                        || y >= size as i32
-                        || z >= size as i32
-                        || (i == 0 && j == 0 && k == 0)
-                    {
-                        continue;
-                    }
-
-                    let current =
-                        3 - i.abs() as usize - j.abs() as usize - k.abs() as usize;
-                    if data[z as usize * size * size + y as usize * size + x as usize] > 0 {
-                        weight += current;
-                    }
-                }
-            }
-        }
+    // Branches taper from the radius of their parent to their own, which fills the joints.
+    fn create_branch(&self, index: usize, volume: &mut Volume) {
+        let branch = &self.branches[index];
+        let radius = self.radius(branch.tips);
+        let from_radius = branch
+            .parent
+            .map_or(radius, |parent| self.radius(self.branches[parent].tips));
+
+        create_capsule(
+            branch.pos - branch.dir,
+            branch.pos,
+            from_radius,
+            radius,
+            self.params.branch_color,
+            volume,
+        );
+    }
 
-        weight
+    fn check_weight(volume: &Volume, pos: IVec3) -> i32 {
+        volume
+            .neighbours(pos)
+            .filter(|(_, voxel)| !voxel.is_empty())
+            .map(|(neighbour, _)| 3 - (neighbour - pos).abs().sum())
+            .sum()
     }
 
-    pub fn create_leaves(&mut self, data: &mut Vec<u32>, size: usize) {
+    pub fn create_leaves(&mut self, volume: &mut Volume) {
         for branch in &self.branches {
-            let x = branch.pos.x.round() as usize;
-            let y = branch.pos.y.round() as usize;
-            let z = branch.pos.z.round() as usize;
-            if !branch.leaf || Self::check_weight(data, size, x, y, z) > 3 {
+            let pos = voxel_pos(branch.pos);
+            // Even the thinnest tips touch a few neighbours, so only crowded tips are skipped
+            if !branch.leaf || Self::check_weight(volume, pos) > 5 {
                 continue;
             }
 
-            for x in 0..LEAF_SIZE {
-                for y in 0..LEAF_SIZE {
-                    for z in 0..LEAF_SIZE {
-                        let c = (x == 0) as usize
-                            + (y == 0) as usize
-                            + (z == 0) as usize
-                            + (x == LEAF_SIZE - 1) as usize
-                            + (y == LEAF_SIZE - 1) as usize
-                            + (z == LEAF_SIZE - 1) as usize;
-
-                        if c >= 2 {
-                            continue;
-                        }
-
-                        data[((branch.pos.z.round() - 2.0) as usize + z) * size * size
-                            + ((branch.pos.y.round() - 2.0) as usize + y) * size
-                            + (branch.pos.x.round() - 2.0) as usize
-                            + x] = LEAF_COLOR;
-                    }
-                }
-            }
+            create_leaf(pos, self.params.leaf_size, self.params.leaf_color, volume);
         }
     }
+}
 
-    pub fn grow(&mut self, data: &mut Vec<u32>, size: usize) {
+impl Grow for SpaceColonization {
+    fn grow(&mut self, volume: &mut Volume) {
         if self.done {
             return;
         }
 
-        if self.width < 1.0 {
-            self.create_leaves(data, size);
+        if self.step >= self.params.steps {
+            self.create_leaves(volume);
             self.done = true;
         }
 
-        let branches = &self.branches;
-        self.leaves.retain(|leaf| {
-            branches.iter().fold(std::f32::MAX, |acc, branch| {
-                distance(&branch.pos, leaf).min(acc)
-            }) > MIN_DIST
-        });
+        // Attraction points that have been reached are removed, the rest pull on their nearest
+        // branch
+        for leaf in &mut self.leaves {
+            if let Some((branch, dist)) = self.recent_branches.nearest(&leaf.pos, leaf.dist) {
+                leaf.branch = branch;
+                leaf.dist = dist;
+            }
+        }
+        self.recent_branches = KdTree::new();
+
+        let min_dist = self.params.min_dist;
+        self.leaves.retain(|leaf| leaf.dist > min_dist);
 
         for leaf in &self.leaves {
-            let branch = self
-                .branches
-                .iter_mut()
-                .fold((std::f32::MAX, None), |acc, branch| {
-                    let dist = distance(&branch.pos, leaf);
-                    if dist < acc.0 {
-                        (dist, Some(branch))
-                    } else {
-                        acc
-                    }
-                })
-                .1
-                .unwrap();
-
-            let dir = (leaf - branch.pos).normalize();
+            let branch = &mut self.branches[leaf.branch];
+            let dir = (leaf.pos - branch.pos).normalize();
             branch.add(dir);
         }
 
-        let new_branches: Vec<_> = self
-            .branches
-            .iter_mut()
-            .filter_map(|x| x.next_option())
-            .collect();
-
-        for branch in &new_branches {
-            Self::create_branch_thickness(branch.pos, branch.dir, self.width as usize, data, size);
+        let count = self.branches.len();
+        let mut forks = Vec::new();
+        let mut new_branches = Vec::new();
+        for (i, branch) in self.branches.iter_mut().enumerate() {
+            let forked = !branch.leaf;
+            if let Some(next) = branch.next_option(i) {
+                if forked {
+                    forks.push(i);
+                }
+                new_branches.push(next);
+            }
         }
 
+        for (i, branch) in new_branches.iter().enumerate() {
+            self.recent_branches.insert(branch.pos, count + i);
+        }
         self.branches.extend(new_branches);
 
-        self.width -= 0.08;
+        // Every fork adds a tip to all of the branches below it
+        let mut thicker = vec![false; self.branches.len()];
+        for fork in forks {
+            let mut parent = Some(fork);
+            while let Some(index) = parent {
+                let tips = self.branches[index].tips;
+                thicker[index] |= self.radius(tips + 1) > self.radius(tips);
+                self.branches[index].tips += 1;
+                parent = self.branches[index].parent;
+            }
+        }
+
+        // Only ever adding voxels is enough, since branches never get thinner
+        for i in 0..self.branches.len() {
+            let parent = self.branches[i].parent;
+            if i >= count || thicker[i] || parent.is_some_and(|parent| thicker[parent]) {
+                self.create_branch(i, volume);
+            }
+        }
+
+        self.step += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::misc::random::Random;
+
+    #[test]
+    fn cone_and_cylinder_presets_grow_leaves() {
+        for name in &["pine", "poplar"] {
+            let params = TreeParams::preset(name).unwrap();
+            let mut volume = Volume::new(IVec3::new(48, 64, 48));
+            let mut rng = StdRng::seed_from_u64(1);
+            let start = Point3::new(24.0, 0.0, 24.0);
+            let mut tree = SpaceColonization::new(start, &params, &mut volume, &mut rng);
+            for _ in 0..=params.steps {
+                tree.grow(&mut volume);
+            }
+
+            let leaves = volume
+                .iter_box(IVec3::zeros(), volume.size().add_scalar(-1))
+                .filter(|(_, voxel)| *voxel == params.leaf_color)
+                .count();
+            assert!(leaves > 0, "{} has no leaves", name);
+        }
+    }
+
+    #[test]
+    fn leaves_are_as_wide_as_their_size() {
+        for size in 1..=6 {
+            let mut volume = Volume::new(IVec3::repeat(16));
+            create_leaf(IVec3::repeat(8), size, LEAF_COLOR, &mut volume);
+
+            let leaves: Vec<_> = volume
+                .iter_box(IVec3::zeros(), volume.size().add_scalar(-1))
+                .filter(|(_, voxel)| *voxel == LEAF_COLOR)
+                .map(|(pos, _)| pos)
+                .collect();
+            let min = leaves.iter().fold(IVec3::repeat(i32::MAX), |min, pos| min.inf(pos));
+            let max = leaves.iter().fold(IVec3::repeat(i32::MIN), |max, pos| max.sup(pos));
+            assert_eq!(max - min, IVec3::repeat(size - 1), "size {}", size);
+        }
+    }
+
+    // Grows trees with more and more attraction points and reports how long a single step takes,
+    // since the tree system grows every tree several steps per frame.
+    //
+    // cargo test --release grow_timings -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn grow_timings() {
+        for &(attractors, radius, steps) in &[
+            (400, 30.0, 50),
+            (4000, 30.0, 50),
+            (10000, 45.0, 130),
+            (20000, 60.0, 200),
+        ] {
+            let params = TreeParams {
+                envelope: Envelope::Sphere { radius },
+                attractors,
+                height: radius + 10.0,
+                steps,
+                ..TreeParams::oak()
+            };
+
+            let mut volume = Volume::new(IVec3::new(256, 160, 256));
+            let mut random = Random::new(11);
+            let start = Point3::new(128.0, 0.0, 128.0);
+            let mut tree = SpaceColonization::new(start, &params, &mut volume, &mut random);
+
+            // The last step places the leaves
+            let steps = steps + 1;
+            let mut total = Duration::default();
+            let mut max = Duration::default();
+            for _ in 0..steps {
+                let time = Instant::now();
+                tree.grow(&mut volume);
+                let elapsed = time.elapsed();
+                total += elapsed;
+                max = max.max(elapsed);
+            }
+
+            println!(
+                "{:>6} attractors: {:>4} steps, {:>8.2?} total, {:>8.2?} mean, {:>8.2?} max",
+                attractors,
+                steps,
+                total,
+                total / steps as u32,
+                max
+            );
+        }
     }
 }
@@ -1,6 +1,8 @@
 use specs::prelude::*;
 
-use crate::systems::{CameraSystem, RenderSystem, TreeSystem};
+use crate::systems::{
+    CameraSystem, DaySystem, LightSystem, RenderSystem, TreeSystem, UploadSystem, WaterSystem,
+};
 
 pub struct Dispatcher<'a, 'b> {
     value: specs::Dispatcher<'a, 'b>,
@@ -12,7 +14,11 @@ impl Dispatcher<'_, '_> {
         let mut world = World::new();
         let mut value = DispatcherBuilder::new()
             .with(CameraSystem::new(), "camera", &[])
+            .with(DaySystem::new(), "day", &[])
             .with(TreeSystem, "tree", &[])
+            .with(WaterSystem, "water", &["tree"])
+            .with(UploadSystem, "upload", &["tree", "water"])
+            .with(LightSystem::default(), "light", &[])
             .with_thread_local(RenderSystem)
             .build();
 
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::math::{self, IVec3};
+use crate::volume::{DirtyBox, Volume, CHUNK_SIZE};
+
+// Emissive voxels light nothing further away than this, which has to match EMISSION_RANGE in the
+// fragment shader.
+pub const RANGE: i32 = 16;
+
+const _: () = assert!(RANGE <= CHUNK_SIZE as i32);
+
+// Bits per axis of a packed emitter position, which the shader unpacks with the same mask.
+const POSITION_BITS: u32 = 10;
+
+// A light grid with one cell per chunk, listing the emissive voxels within RANGE of the chunk so
+// the shader only samples emitters that can reach a hit. The grid starts with an offset and count
+// for every chunk in page table order, followed by the lists, where every emitter is stored as its
+// position packed into POSITION_BITS per axis, so the volume can't be larger than 1024 voxels
+// along any axis.
+pub struct Emitters {
+    chunks: IVec3,
+    emitters: HashMap<IVec3, Vec<IVec3>>,
+    pub grid: Vec<u32>,
+}
+
+impl Emitters {
+    pub fn new(volume: &Volume) -> Self {
+        assert!(
+            volume.size().max() <= 1 << POSITION_BITS,
+            "volume of size {} is too large to pack emitter positions",
+            volume.size()
+        );
+
+        let mut emitters = Self {
+            chunks: volume.chunks_per_axis(),
+            emitters: HashMap::new(),
+            grid: Vec::new(),
+        };
+
+        for key in volume.chunks().map(|(key, _)| *key) {
+            let min = key * CHUNK_SIZE as i32;
+            emitters.update_chunk(volume, key, min, min.add_scalar(CHUNK_SIZE as i32 - 1));
+        }
+        emitters.build_grid();
+
+        emitters
+    }
+
+    // Rescans the dirty boxes and returns whether the grid changed and has to be uploaded again.
+    pub fn update(&mut self, volume: &Volume, dirty: &HashMap<IVec3, DirtyBox>) -> bool {
+        let mut changed = false;
+        for (key, dirty) in dirty {
+            changed |= self.update_chunk(volume, *key, dirty.min, dirty.max);
+        }
+
+        if changed {
+            self.build_grid();
+        }
+        changed
+    }
+
+    fn update_chunk(&mut self, volume: &Volume, key: IVec3, min: IVec3, max: IVec3) -> bool {
+        let inside = |pos: &IVec3| *pos == pos.sup(&min).inf(&max);
+        let emitters = self.emitters.entry(key).or_default();
+        let before = emitters.len();
+        emitters.retain(|pos| !inside(pos));
+        let kept = emitters.len();
+
+        let found = volume
+            .iter_box(min, max)
+            .filter(|(_, voxel)| voxel.is_emissive())
+            .map(|(pos, _)| pos);
+        emitters.extend(found);
+
+        kept != before || emitters.len() != before
+    }
+
+    fn build_grid(&mut self) {
+        let chunks = self.chunks;
+        let count = chunks.iter().product::<i32>() as usize;
+        self.grid = vec![0; count * 2];
+
+        for key in math::box_positions(IVec3::zeros(), chunks.add_scalar(-1)) {
+            let min = key * CHUNK_SIZE as i32;
+            let max = min.add_scalar(CHUNK_SIZE as i32 - 1);
+            let index = ((key.z * chunks.y + key.y) * chunks.x + key.x) as usize;
+            self.grid[index * 2] = self.grid.len() as u32;
+
+            // RANGE is at most a chunk, so only the neighbouring chunks can hold emitters in range
+            for neighbour in math::box_positions(key.add_scalar(-1), key.add_scalar(1)) {
+                let emitters = match self.emitters.get(&neighbour) {
+                    Some(emitters) => emitters,
+                    None => continue,
+                };
+
+                for pos in emitters {
+                    let closest = pos.sup(&min).inf(&max);
+                    if (pos - closest).map(|value| value * value).sum() <= RANGE * RANGE {
+                        let packed = pos.x as u32
+                            | (pos.y as u32) << POSITION_BITS
+                            | (pos.z as u32) << (2 * POSITION_BITS);
+                        self.grid.push(packed);
+                    }
+                }
+            }
+
+            self.grid[index * 2 + 1] = self.grid.len() as u32 - self.grid[index * 2];
+        }
+    }
+}
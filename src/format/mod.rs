@@ -0,0 +1,2 @@
+pub mod vox;
+pub mod world;
\ No newline at end of file
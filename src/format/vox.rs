@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use nalgebra::Vector3;
+
+use crate::math;
+use crate::volume::Volume;
+use crate::voxel::Voxel;
+
+// MagicaVoxel files are z-up while the world is y-up, models can be at most 256 voxels along each
+// axis and their position in the scene is given by the translation of the centre of the model.
+const MAGIC: [u8; 4] = *b"VOX ";
+const VERSION: i32 = 150;
+const MAX_MODEL_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(io::Error),
+    InvalidMagic,
+    InvalidChunk([u8; 4]),
+    Truncated,
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VoxError::Io(error) => write!(f, "{}", error),
+            VoxError::InvalidMagic => write!(f, "Not a MagicaVoxel file"),
+            VoxError::InvalidChunk(id) => {
+                write!(f, "Invalid {} chunk", String::from_utf8_lossy(id))
+            }
+            VoxError::Truncated => write!(f, "MagicaVoxel file is truncated"),
+        }
+    }
+}
+
+impl Error for VoxError {}
+
+impl From<io::Error> for VoxError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => VoxError::Truncated,
+            _ => VoxError::Io(error),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Material {
+    Diffuse,
+    Glass(f32),
+    Metal(f32),
+    Emit(f32),
+}
+
+enum Node {
+    Transform(Vector3<i32>, i32),
+    Group(Vec<i32>),
+    Shape(Vec<i32>),
+}
+
+struct Model {
+    size: Vector3<i32>,
+    voxels: Vec<[u8; 4]>,
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VoxError> {
+        if len > self.bytes.len() {
+            return Err(VoxError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> Result<i32, VoxError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn len(&mut self) -> Result<usize, VoxError> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Err(VoxError::Truncated);
+        }
+
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, VoxError> {
+        let len = self.len()?;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>, VoxError> {
+        let len = self.len()?;
+        let mut dict = HashMap::new();
+        for _ in 0..len {
+            let key = self.string()?;
+            let value = self.string()?;
+            dict.insert(key, value);
+        }
+
+        Ok(dict)
+    }
+}
+
+fn to_world(pos: Vector3<i32>) -> Vector3<i32> {
+    Vector3::new(pos.x, pos.z, -pos.y)
+}
+
+fn to_vox(pos: Vector3<i32>) -> Vector3<i32> {
+    Vector3::new(pos.x, -pos.z, pos.y)
+}
+
+fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0; 4]; 256];
+    let steps = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let mut i = 1;
+
+    for &r in &steps {
+        for &g in &steps {
+            for &b in &steps {
+                if i < 216 {
+                    palette[i] = [r, g, b, 0xff];
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for &channel in &[2, 1, 0] {
+        for &value in &ramp {
+            palette[i][channel] = value;
+            palette[i][3] = 0xff;
+            i += 1;
+        }
+    }
+
+    for &value in &ramp {
+        palette[i] = [value, value, value, 0xff];
+        i += 1;
+    }
+
+    palette
+}
+
+fn to_voxel(color: [u8; 4], material: Material) -> Voxel {
+    let weight = |value: f32| (value.clamp(0.0, 1.0) * 15.0).round() as u8;
+
+    match material {
+        Material::Diffuse => Voxel::solid(color[0], color[1], color[2]),
+        Material::Glass(transparency) => Voxel::new(
+            color[0],
+            color[1],
+            color[2],
+            weight(1.0 - transparency).max(1),
+            0,
+        ),
+        // Reflectivity 15 is taken by emissive voxels
+        Material::Metal(metalness) => {
+            Voxel::new(color[0], color[1], color[2], 15, weight(metalness).min(14))
+        }
+        Material::Emit(emission) => Voxel::emissive(color[0], color[1], color[2], weight(emission)),
+    }
+}
+
+fn to_material(voxel: Voxel) -> Material {
+    if voxel.is_emissive() {
+        Material::Emit(voxel.emission() as f32 / 15.0)
+    } else if voxel.transparency() < 15 {
+        Material::Glass(1.0 - voxel.transparency() as f32 / 15.0)
+    } else if voxel.reflectivity() > 0 {
+        Material::Metal(voxel.reflectivity() as f32 / 15.0)
+    } else {
+        Material::Diffuse
+    }
+}
+
+pub fn read<R: Read>(mut reader: R) -> Result<Vec<(Vector3<i32>, Voxel)>, VoxError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut parser = Parser { bytes: &bytes };
+    if parser.take(4)? != MAGIC {
+        return Err(VoxError::InvalidMagic);
+    }
+    parser.i32()?;
+
+    let mut models = Vec::new();
+    let mut palette = default_palette();
+    let mut materials = HashMap::new();
+    let mut nodes = HashMap::new();
+
+    if parser.take(4)? != b"MAIN" {
+        return Err(VoxError::InvalidChunk(*b"MAIN"));
+    }
+    let content = parser.len()?;
+    let children = parser.len()?;
+    parser.take(content)?;
+    let mut parser = Parser {
+        bytes: parser.take(children)?,
+    };
+
+    while !parser.bytes.is_empty() {
+        let id = parser.take(4)?;
+        let id = [id[0], id[1], id[2], id[3]];
+        let content = parser.len()?;
+        let children = parser.len()?;
+        let mut chunk = Parser {
+            bytes: parser.take(content)?,
+        };
+        parser.take(children)?;
+
+        match &id {
+            b"SIZE" => {
+                let size = Vector3::new(chunk.i32()?, chunk.i32()?, chunk.i32()?);
+                models.push(Model {
+                    size,
+                    voxels: Vec::new(),
+                });
+            }
+            b"XYZI" => {
+                let model = models.last_mut().ok_or(VoxError::InvalidChunk(id))?;
+                let len = chunk.len()?;
+                for voxel in chunk.take(len * 4)?.chunks_exact(4) {
+                    model.voxels.push([voxel[0], voxel[1], voxel[2], voxel[3]]);
+                }
+            }
+            b"RGBA" => {
+                for i in 0..255 {
+                    let color = chunk.take(4)?;
+                    palette[i + 1] = [color[0], color[1], color[2], color[3]];
+                }
+            }
+            b"MATL" => {
+                let index = chunk.i32()?;
+                let dict = chunk.dict()?;
+                let value = |keys: &[&str]| {
+                    keys.iter()
+                        .filter_map(|key| dict.get(*key))
+                        .filter_map(|value| value.parse::<f32>().ok())
+                        .next()
+                        .unwrap_or(0.5)
+                };
+
+                let material = match dict.get("_type").map(|value| value.as_str()) {
+                    Some("_glass") => Material::Glass(value(&["_trans", "_alpha", "_weight"])),
+                    Some("_metal") => Material::Metal(value(&["_metal", "_weight"])),
+                    Some("_emit") => Material::Emit(value(&["_emit", "_weight"])),
+                    _ => Material::Diffuse,
+                };
+                materials.insert(index, material);
+            }
+            b"nTRN" => {
+                let node = chunk.i32()?;
+                chunk.dict()?;
+                let child = chunk.i32()?;
+                chunk.i32()?;
+                chunk.i32()?;
+
+                let mut translation = Vector3::zeros();
+                if chunk.len()? > 0 {
+                    if let Some(value) = chunk.dict()?.get("_t") {
+                        let values: Vec<i32> =
+                            value.split(' ').filter_map(|value| value.parse().ok()).collect();
+                        if values.len() == 3 {
+                            translation = Vector3::new(values[0], values[1], values[2]);
+                        }
+                    }
+                }
+                nodes.insert(node, Node::Transform(translation, child));
+            }
+            b"nGRP" => {
+                let node = chunk.i32()?;
+                chunk.dict()?;
+                let len = chunk.len()?;
+                let children = (0..len)
+                    .map(|_| chunk.i32())
+                    .collect::<Result<Vec<_>, _>>()?;
+                nodes.insert(node, Node::Group(children));
+            }
+            b"nSHP" => {
+                let node = chunk.i32()?;
+                chunk.dict()?;
+                let len = chunk.len()?;
+                let mut shapes = Vec::with_capacity(len);
+                for _ in 0..len {
+                    shapes.push(chunk.i32()?);
+                    chunk.dict()?;
+                }
+                nodes.insert(node, Node::Shape(shapes));
+            }
+            _ => {}
+        }
+    }
+
+    let mut offsets = vec![None; models.len()];
+    let mut stack = vec![(0, Vector3::zeros())];
+    while let Some((node, translation)) = stack.pop() {
+        match nodes.get(&node) {
+            Some(Node::Transform(offset, child)) => stack.push((*child, translation + offset)),
+            Some(Node::Group(children)) => {
+                stack.extend(children.iter().map(|&child| (child, translation)))
+            }
+            Some(Node::Shape(shapes)) => {
+                for &shape in shapes {
+                    if let Some(offset) = offsets.get_mut(shape as usize) {
+                        *offset = Some(translation);
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    let mut voxels = Vec::new();
+    for (model, offset) in models.iter().zip(offsets) {
+        let min = match offset {
+            Some(offset) => offset - model.size / 2,
+            None => Vector3::zeros(),
+        };
+
+        for &[x, y, z, index] in &model.voxels {
+            let material = *materials.get(&(index as i32)).unwrap_or(&Material::Diffuse);
+            let pos = min + Vector3::new(x as i32, y as i32, z as i32);
+            voxels.push((to_world(pos), to_voxel(palette[index as usize], material)));
+        }
+    }
+
+    Ok(voxels)
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(content);
+    bytes.extend_from_slice(children);
+}
+
+fn write_i32s(bytes: &mut Vec<u8>, values: &[i32]) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn write_dict(bytes: &mut Vec<u8>, dict: &[(&str, String)]) {
+    write_i32s(bytes, &[dict.len() as i32]);
+    for (key, value) in dict {
+        for string in &[key.as_bytes(), value.as_bytes()] {
+            write_i32s(bytes, &[string.len() as i32]);
+            bytes.extend_from_slice(string);
+        }
+    }
+}
+
+fn quantize(voxel: Voxel, shift: u32) -> Voxel {
+    let channel = |value: u8| (value >> shift << shift) | (1 << shift >> 1);
+    voxel.with_rgb(channel(voxel.r()), channel(voxel.g()), channel(voxel.b()))
+}
+
+// Squared distance between the colours and materials of two voxels, with a material step weighing as
+// much as 17 steps of a colour channel so both span the same range.
+fn distance(a: Voxel, b: Voxel) -> i32 {
+    let colour = |value: u8| value as i32;
+    let material = |value: u8| value as i32 * 17;
+    [
+        colour(a.r()) - colour(b.r()),
+        colour(a.g()) - colour(b.g()),
+        colour(a.b()) - colour(b.b()),
+        material(a.transparency()) - material(b.transparency()),
+        material(a.reflectivity()) - material(b.reflectivity()),
+        material(a.emission()) - material(b.emission()),
+    ]
+    .iter()
+    .map(|value| value * value)
+    .sum()
+}
+
+pub fn write<W: Write>(mut writer: W, volume: &Volume) -> io::Result<()> {
+    let size = volume.size();
+    let vox_size = to_vox(size).abs();
+    let mut voxels = Vec::new();
+    for (pos, voxel) in volume.iter_box(Vector3::zeros(), size.add_scalar(-1)) {
+        if !voxel.is_empty() {
+            voxels.push((to_vox(pos) + Vector3::new(0, vox_size.y - 1, 0), voxel));
+        }
+    }
+
+    // Ordered by how often a voxel occurs, so the most common ones are kept if the palette is full
+    let quantized_palette = |shift| {
+        let mut counts: HashMap<Voxel, usize> = HashMap::new();
+        for &(_, voxel) in &voxels {
+            *counts.entry(quantize(voxel, shift)).or_default() += 1;
+        }
+
+        let mut palette: Vec<_> = counts.into_iter().collect();
+        palette.sort_by_key(|&(voxel, count)| (std::cmp::Reverse(count), voxel.to_bits()));
+        palette.into_iter().map(|(voxel, _)| voxel).collect::<Vec<_>>()
+    };
+
+    let mut shift = 0;
+    let mut palette = quantized_palette(shift);
+    while palette.len() > 255 && shift < 7 {
+        shift += 1;
+        palette = quantized_palette(shift);
+    }
+
+    // Colours alone can't always be reduced far enough, as every colour can come in many materials.
+    // The least common voxels are then written as the closest voxel that is left.
+    let dropped = palette.split_off(palette.len().min(255));
+    let mut indices: HashMap<_, _> = palette
+        .iter()
+        .enumerate()
+        .map(|(i, voxel)| (*voxel, i as u8 + 1))
+        .collect();
+    for voxel in dropped {
+        let closest = (0..palette.len())
+            .min_by_key(|&i| distance(voxel, palette[i]))
+            .expect("a full palette has voxels left");
+        indices.insert(voxel, closest as u8 + 1);
+    }
+
+    let max_model_size = Vector3::repeat(MAX_MODEL_SIZE as i32);
+    let tiles = vox_size.map(|value| (value - 1) / MAX_MODEL_SIZE as i32);
+    let mut models: HashMap<Vector3<i32>, Vec<[u8; 4]>> = HashMap::new();
+    for (pos, voxel) in &voxels {
+        let tile = pos / MAX_MODEL_SIZE as i32;
+        let local = pos - tile * MAX_MODEL_SIZE as i32;
+        let index = indices[&quantize(*voxel, shift)];
+        models
+            .entry(tile)
+            .or_default()
+            .push([local.x as u8, local.y as u8, local.z as u8, index]);
+    }
+
+    let mut tiles: Vec<_> = math::box_positions(Vector3::zeros(), tiles)
+        .filter(|tile| models.contains_key(tile))
+        .collect();
+    if tiles.is_empty() {
+        tiles.push(Vector3::zeros());
+    }
+
+    let mut children = Vec::new();
+    let mut content = Vec::new();
+    write_i32s(&mut content, &[tiles.len() as i32]);
+    write_chunk(&mut children, b"PACK", &content, &[]);
+
+    for tile in &tiles {
+        let model_size = (vox_size - tile * MAX_MODEL_SIZE as i32).inf(&max_model_size);
+        let model = models.remove(tile).unwrap_or_default();
+
+        content.clear();
+        write_i32s(&mut content, &[model_size.x, model_size.y, model_size.z]);
+        write_chunk(&mut children, b"SIZE", &content, &[]);
+
+        content.clear();
+        write_i32s(&mut content, &[model.len() as i32]);
+        for voxel in &model {
+            content.extend_from_slice(voxel);
+        }
+        write_chunk(&mut children, b"XYZI", &content, &[]);
+    }
+
+    content.clear();
+    write_i32s(&mut content, &[0]);
+    write_dict(&mut content, &[]);
+    write_i32s(&mut content, &[1, -1, -1, 1]);
+    write_dict(&mut content, &[]);
+    write_chunk(&mut children, b"nTRN", &content, &[]);
+
+    content.clear();
+    write_i32s(&mut content, &[1]);
+    write_dict(&mut content, &[]);
+    write_i32s(&mut content, &[tiles.len() as i32]);
+    write_i32s(
+        &mut content,
+        &(0..tiles.len() as i32).map(|i| 2 + i * 2).collect::<Vec<_>>(),
+    );
+    write_chunk(&mut children, b"nGRP", &content, &[]);
+
+    for (i, tile) in tiles.iter().enumerate() {
+        let model_size = (vox_size - tile * MAX_MODEL_SIZE as i32).inf(&max_model_size);
+        let center = tile * MAX_MODEL_SIZE as i32 + model_size / 2;
+        let node = 2 + i as i32 * 2;
+
+        content.clear();
+        write_i32s(&mut content, &[node]);
+        write_dict(&mut content, &[]);
+        write_i32s(&mut content, &[node + 1, -1, 0, 1]);
+        write_dict(
+            &mut content,
+            &[("_t", format!("{} {} {}", center.x, center.y, center.z))],
+        );
+        write_chunk(&mut children, b"nTRN", &content, &[]);
+
+        content.clear();
+        write_i32s(&mut content, &[node + 1]);
+        write_dict(&mut content, &[]);
+        write_i32s(&mut content, &[1, i as i32]);
+        write_dict(&mut content, &[]);
+        write_chunk(&mut children, b"nSHP", &content, &[]);
+    }
+
+    content.clear();
+    for i in 0..256 {
+        let color = match palette.get(i) {
+            Some(voxel) => [voxel.r(), voxel.g(), voxel.b(), 0xff],
+            None => [0; 4],
+        };
+        content.extend_from_slice(&color);
+    }
+    write_chunk(&mut children, b"RGBA", &content, &[]);
+
+    for (i, voxel) in palette.iter().enumerate() {
+        let dict = match to_material(*voxel) {
+            Material::Diffuse => continue,
+            Material::Glass(transparency) => {
+                vec![("_type", "_glass".to_string()), ("_trans", transparency.to_string())]
+            }
+            Material::Metal(metalness) => {
+                vec![("_type", "_metal".to_string()), ("_metal", metalness.to_string())]
+            }
+            Material::Emit(emission) => {
+                vec![("_type", "_emit".to_string()), ("_emit", emission.to_string())]
+            }
+        };
+
+        content.clear();
+        write_i32s(&mut content, &[i as i32 + 1]);
+        write_dict(&mut content, &dict);
+        write_chunk(&mut children, b"MATL", &content, &[]);
+    }
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&MAGIC);
+    write_i32s(&mut bytes, &[VERSION]);
+    write_chunk(&mut bytes, b"MAIN", &[], &children);
+
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let size = Vector3::new(16, 4, 3);
+        let mut volume = Volume::new(size);
+        for i in 0..15 {
+            let x = i as i32;
+            volume.set(Vector3::new(x, 0, 0), Voxel::solid(i * 16, 255 - i * 16, 7));
+            volume.set(Vector3::new(x, 1, 1), Voxel::new(i * 8, 0, 255, i % 14 + 1, 0));
+            volume.set(Vector3::new(x, 2, 2), Voxel::new(0, i * 8, 0, 15, i.min(14)));
+            volume.set(Vector3::new(x, 3, 0), Voxel::emissive(255, i * 16, 0, i + 1));
+        }
+        volume.set(size.add_scalar(-1), Voxel::solid(1, 2, 3));
+
+        let path = std::env::temp_dir().join(format!("voxel-{}.vox", std::process::id()));
+        volume.save_vox(&path).unwrap();
+        let loaded = Volume::from_vox_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.size(), size);
+        for (pos, voxel) in volume.iter_box(Vector3::zeros(), size.add_scalar(-1)) {
+            assert_eq!(loaded.get(pos), voxel, "at {:?}", pos);
+        }
+    }
+
+    #[test]
+    fn full_palette_uses_closest_voxels() {
+        // 8 colours that stay distinct when quantized, in every material a .vox file can hold
+        let mut kinds = Vec::new();
+        for colour in 0..8 {
+            let channel = |bit: u8| if colour & bit == 0 { 0 } else { 255 };
+            let (r, g, b) = (channel(1), channel(2), channel(4));
+            kinds.push(Voxel::solid(r, g, b));
+            for weight in 1..15 {
+                kinds.push(Voxel::new(r, g, b, weight, 0));
+                kinds.push(Voxel::new(r, g, b, 15, weight));
+            }
+            for intensity in 1..16 {
+                kinds.push(Voxel::emissive(r, g, b, intensity));
+            }
+        }
+        assert!(kinds.len() > 255);
+
+        // The first half of the colours is twice as common and has to survive
+        let common = kinds.len() / 2;
+        let placed: Vec<_> = kinds.iter().chain(&kinds[..common]).copied().collect();
+        let size = Vector3::new(placed.len() as i32, 1, 1);
+        let mut volume = Volume::new(size);
+        for (x, voxel) in placed.iter().enumerate() {
+            volume.set(Vector3::new(x as i32, 0, 0), *voxel);
+        }
+
+        let path = std::env::temp_dir().join(format!("voxel-palette-{}.vox", std::process::id()));
+        volume.save_vox(&path).unwrap();
+        let loaded = Volume::from_vox_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        let mut palette: Vec<_> = (0..kinds.len())
+            .map(|x| loaded.get(Vector3::new(x as i32, 0, 0)))
+            .collect();
+        palette.sort_by_key(|voxel| voxel.to_bits());
+        palette.dedup();
+        assert!(palette.len() <= 255);
+
+        for (x, voxel) in kinds.iter().enumerate() {
+            let expected = quantize(*voxel, 7);
+            let actual = loaded.get(Vector3::new(x as i32, 0, 0));
+            if x < common {
+                assert_eq!(actual, expected, "at {}", x);
+            } else {
+                let closest = palette.iter().map(|&kept| distance(expected, kept)).min();
+                assert_eq!(Some(distance(expected, actual)), closest, "at {}", x);
+            }
+        }
+    }
+
+    #[test]
+    fn caps_metal_below_emissive() {
+        let voxel = to_voxel([255, 255, 255, 255], Material::Metal(1.0));
+        assert_eq!(voxel.reflectivity(), 14);
+        assert!(!voxel.is_emissive());
+    }
+}
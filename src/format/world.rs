@@ -0,0 +1,326 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::voxel::Voxel;
+
+// World files start with a header of little-endian values:
+//
+//   magic        4 bytes  "VOXW"
+//   version      u32      currently 3
+//   width        u32
+//   height       u32
+//   depth        u32
+//   origin       3 x i32  world position of the first voxel, not present in version 1
+//   compression  u32      0 = none, 1 = run-length
+//
+// followed by width * height * depth voxels ordered by x, then y, then z. Uncompressed files store
+// each voxel as a u32, run-length encoded files store (count: u32, voxel: u32) pairs instead.
+// Version 1 files have no origin and are centred around 0. Files before version 3 have no emissive
+// voxels, so a reflectivity of 15 is read as 14 instead.
+const MAGIC: [u8; 4] = *b"VOXW";
+const VERSION: u32 = 3;
+const MAX_VOXELS: u64 = 1 << 30;
+// The header alone can not be trusted with the size of an allocation, so voxels are read in runs of
+// at most this many and the buffer only grows as data arrives.
+const READ_VOXELS: usize = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    RunLength = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub origin: [i32; 3],
+    pub compression: Compression,
+}
+
+impl Header {
+    pub fn len(&self) -> usize {
+        self.width as usize * self.height as usize * self.depth as usize
+    }
+}
+
+#[derive(Debug)]
+pub enum WorldError {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    UnsupportedCompression(u32),
+    InvalidDimensions(u32, u32, u32),
+    Truncated,
+    Oversized,
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorldError::Io(error) => write!(f, "{}", error),
+            WorldError::InvalidMagic => write!(f, "Not a world file"),
+            WorldError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported world file version {}", version)
+            }
+            WorldError::UnsupportedCompression(compression) => {
+                write!(f, "Unsupported compression {}", compression)
+            }
+            WorldError::InvalidDimensions(width, height, depth) => {
+                write!(f, "Invalid world dimensions {}x{}x{}", width, height, depth)
+            }
+            WorldError::Truncated => write!(f, "World file is truncated"),
+            WorldError::Oversized => {
+                write!(f, "World file contains more data than its header describes")
+            }
+        }
+    }
+}
+
+impl Error for WorldError {}
+
+impl From<io::Error> for WorldError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => WorldError::Truncated,
+            _ => WorldError::Io(error),
+        }
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, WorldError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read<R: Read>(mut reader: R) -> Result<(Header, Vec<Voxel>), WorldError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(WorldError::InvalidMagic);
+    }
+
+    let version = read_u32(&mut reader)?;
+    if version == 0 || version > VERSION {
+        return Err(WorldError::UnsupportedVersion(version));
+    }
+
+    let width = read_u32(&mut reader)?;
+    let height = read_u32(&mut reader)?;
+    let depth = read_u32(&mut reader)?;
+    if width == 0
+        || height == 0
+        || depth == 0
+        || width as u64 * height as u64 * depth as u64 > MAX_VOXELS
+    {
+        return Err(WorldError::InvalidDimensions(width, height, depth));
+    }
+
+    let origin = if version >= 2 {
+        [
+            read_u32(&mut reader)? as i32,
+            read_u32(&mut reader)? as i32,
+            read_u32(&mut reader)? as i32,
+        ]
+    } else {
+        [
+            -(width as i32 / 2),
+            -(height as i32 / 2),
+            -(depth as i32 / 2),
+        ]
+    };
+
+    let compression = match read_u32(&mut reader)? {
+        0 => Compression::None,
+        1 => Compression::RunLength,
+        compression => return Err(WorldError::UnsupportedCompression(compression)),
+    };
+
+    let header = Header {
+        width,
+        height,
+        depth,
+        origin,
+        compression,
+    };
+
+    let len = header.len();
+    let mut voxels = Vec::new();
+    match compression {
+        Compression::None => {
+            let mut buffer = vec![0; len.min(READ_VOXELS) * 4];
+            while voxels.len() < len {
+                let bytes = &mut buffer[..(len - voxels.len()).min(READ_VOXELS) * 4];
+                reader.read_exact(bytes)?;
+                voxels.extend(bytes.chunks_exact(4).map(|bytes| {
+                    Voxel::from_bits(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                }));
+            }
+        }
+        Compression::RunLength => {
+            while voxels.len() < len {
+                let count = read_u32(&mut reader)? as usize;
+                let voxel = Voxel::from_bits(read_u32(&mut reader)?);
+                if count > len - voxels.len() {
+                    return Err(WorldError::Oversized);
+                }
+
+                voxels.resize(voxels.len() + count, voxel);
+            }
+        }
+    }
+
+    if reader.read(&mut [0])? > 0 {
+        return Err(WorldError::Oversized);
+    }
+
+    if version < 3 {
+        for voxel in &mut voxels {
+            if voxel.is_emissive() {
+                *voxel = Voxel::from_bits(voxel.to_bits() & !(1 << 24));
+            }
+        }
+    }
+
+    Ok((header, voxels))
+}
+
+pub fn write<W, I>(mut writer: W, header: &Header, voxels: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = Voxel>,
+{
+    writer.write_all(&MAGIC)?;
+    for value in &[
+        VERSION,
+        header.width,
+        header.height,
+        header.depth,
+        header.origin[0] as u32,
+        header.origin[1] as u32,
+        header.origin[2] as u32,
+        header.compression as u32,
+    ] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    match header.compression {
+        Compression::None => {
+            for voxel in voxels {
+                writer.write_all(&voxel.to_bits().to_le_bytes())?;
+            }
+        }
+        Compression::RunLength => {
+            let mut run: Option<(u32, Voxel)> = None;
+            for voxel in voxels {
+                run = match run {
+                    Some((count, current)) if current == voxel && count < u32::MAX => {
+                        Some((count + 1, current))
+                    }
+                    Some((count, current)) => {
+                        writer.write_all(&count.to_le_bytes())?;
+                        writer.write_all(&current.to_bits().to_le_bytes())?;
+                        Some((1, voxel))
+                    }
+                    None => Some((1, voxel)),
+                };
+            }
+
+            if let Some((count, current)) = run {
+                writer.write_all(&count.to_le_bytes())?;
+                writer.write_all(&current.to_bits().to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(compression: Compression) -> Header {
+        Header {
+            width: 3,
+            height: 2,
+            depth: 2,
+            origin: [-1, 0, 4],
+            compression,
+        }
+    }
+
+    fn voxels() -> Vec<Voxel> {
+        (0..12).map(|i| Voxel::solid(i / 4, 0, 0)).collect()
+    }
+
+    fn file(compression: Compression) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &header(compression), voxels()).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        for &compression in &[Compression::None, Compression::RunLength] {
+            let (read_header, read_voxels) = read(&file(compression)[..]).unwrap();
+            assert_eq!(read_header, header(compression));
+            assert_eq!(read_voxels, voxels());
+        }
+    }
+
+    #[test]
+    fn fully_reflective_before_version_3() {
+        let mirror = Voxel::new(0x80, 0x80, 0x80, 15, 15);
+        let mut bytes = Vec::new();
+        write(&mut bytes, &header(Compression::RunLength), vec![mirror; 12]).unwrap();
+
+        let (_, read_voxels) = read(&bytes[..]).unwrap();
+        assert!(read_voxels.iter().all(|voxel| voxel.is_emissive()));
+
+        bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+        let (_, read_voxels) = read(&bytes[..]).unwrap();
+        assert_eq!(read_voxels, vec![Voxel::new(0x80, 0x80, 0x80, 15, 14); 12]);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = file(Compression::None);
+        bytes[..4].copy_from_slice(b"VOXX");
+        assert!(matches!(read(&bytes[..]), Err(WorldError::InvalidMagic)));
+    }
+
+    #[test]
+    fn unknown_version() {
+        for &version in &[0, VERSION + 1] {
+            let mut bytes = file(Compression::None);
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+            assert!(matches!(
+                read(&bytes[..]),
+                Err(WorldError::UnsupportedVersion(read_version)) if read_version == version
+            ));
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        for &compression in &[Compression::None, Compression::RunLength] {
+            let bytes = file(compression);
+            for len in 0..bytes.len() {
+                assert!(matches!(read(&bytes[..len]), Err(WorldError::Truncated)));
+            }
+        }
+    }
+
+    #[test]
+    fn huge_header_without_data() {
+        let mut bytes = file(Compression::None);
+        for value in bytes[8..20].chunks_exact_mut(4) {
+            value.copy_from_slice(&1024u32.to_le_bytes());
+        }
+        assert!(matches!(read(&bytes[..]), Err(WorldError::Truncated)));
+    }
+}
@@ -0,0 +1,113 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector3;
+
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
+
+use rand::Rng;
+
+use crate::math::{self, IVec3};
+use crate::volume::{Volume, CHUNK_SIZE};
+use crate::voxel::Voxel;
+
+const CAVERN_OCTAVES: usize = 4;
+
+// Worm tunnels are random walks that carve spheres along their path, caverns are carved wherever
+// a 3D noise is above 1 - 2 * cavern_density. Worms is the number of tunnels per 32x32 columns.
+// Nothing within min_depth voxels of the surface is carved, so the surface stays intact.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaveConfig {
+    pub worms: f64,
+    pub worm_length: usize,
+    pub worm_radius: (f32, f32),
+    pub cavern_frequency: f64,
+    pub cavern_density: f64,
+    pub min_depth: i32,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            worms: 1.5,
+            worm_length: 120,
+            worm_radius: (1.5, 3.5),
+            cavern_frequency: 0.03,
+            cavern_density: 0.25,
+            min_depth: 4,
+        }
+    }
+}
+
+// Removes voxels below the surface. Only air is written, so the walls of the caves keep the colour
+// of their depth below the surface.
+pub fn carve<R: Rng>(volume: &mut Volume, config: &CaveConfig, rng: &mut R) {
+    let size = volume.size();
+
+    // The highest voxel that may be carved in every column, or -1 if the column is left alone
+    let mut ceilings = vec![-1; (size.x * size.z) as usize];
+    for z in 0..size.z {
+        for x in 0..size.x {
+            let surface = (0..size.y)
+                .rev()
+                .find(|&y| !volume.get(IVec3::new(x, y, z)).is_empty());
+
+            if let Some(surface) = surface {
+                ceilings[(z * size.x + x) as usize] = surface - config.min_depth;
+            }
+        }
+    }
+    let ceiling = |pos: IVec3| ceilings[(pos.z * size.x + pos.x) as usize];
+
+    let fbm = Fbm::new()
+        .set_seed(rng.gen())
+        .set_octaves(CAVERN_OCTAVES)
+        .set_frequency(config.cavern_frequency);
+    let threshold = 1.0 - 2.0 * config.cavern_density;
+
+    for z in 0..size.z {
+        for x in 0..size.x {
+            for y in 0..=ceiling(IVec3::new(x, 0, z)) {
+                if fbm.get([x as f64, y as f64, z as f64]) > threshold {
+                    volume.set(IVec3::new(x, y, z), Voxel::EMPTY);
+                }
+            }
+        }
+    }
+
+    let columns = (size.x * size.z) as f64 / (CHUNK_SIZE * CHUNK_SIZE) as f64;
+    let worms = (config.worms * columns).round() as usize;
+    for _ in 0..worms {
+        let x = rng.gen_range(0..size.x);
+        let z = rng.gen_range(0..size.z);
+        let top = ceiling(IVec3::new(x, 0, z));
+        if top <= 0 {
+            continue;
+        }
+
+        let mut pos = Vector3::new(x as f32, rng.gen_range(0..top) as f32, z as f32);
+        let mut yaw = rng.gen::<f32>() * 2.0 * PI;
+        let mut pitch: f32 = rng.gen_range(-0.5..0.5);
+        let radius = rng.gen_range(config.worm_radius.0..=config.worm_radius.1);
+
+        for _ in 0..config.worm_length {
+            let min = pos.map(|value| (value - radius).floor() as i32);
+            let max = pos.map(|value| (value + radius).ceil() as i32);
+            for voxel in math::box_positions(min, max) {
+                if volume.contains(voxel)
+                    && voxel.y <= ceiling(voxel)
+                    && (voxel.map(|value| value as f32) - pos).norm() <= radius
+                {
+                    volume.set(voxel, Voxel::EMPTY);
+                }
+            }
+
+            pos += Vector3::new(
+                yaw.cos() * pitch.cos(),
+                pitch.sin(),
+                yaw.sin() * pitch.cos(),
+            );
+            yaw += rng.gen_range(-0.3..0.3);
+            pitch = (pitch + rng.gen_range(-0.2..0.2)).clamp(-0.8, 0.8);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector2;
+
+use rand::Rng;
+
+// Droplets carry at least this much sediment per unit of water, so they keep eroding on flat ground.
+const MIN_CAPACITY: f32 = 0.01;
+const GRAVITY: f32 = 4.0;
+
+// Hydraulic erosion lets droplets run downhill from random spots, where they pick up sediment
+// when they speed up and drop it when they slow down or their water evaporates. Droplets is the
+// number of droplets per column. Inertia is how much a droplet keeps its direction instead of
+// following the slope and capacity is how much sediment it can carry for its speed and water.
+// Erode and deposit are the fractions of the difference to its capacity that it picks up or drops
+// each step, and eroding takes from all columns within radius.
+//
+// Thermal erosion then lets material slide down wherever the height difference to a neighbour is
+// more than talus per column of distance, moving thermal_rate of the excess every step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErosionConfig {
+    pub droplets: f32,
+    pub lifetime: usize,
+    pub inertia: f32,
+    pub capacity: f32,
+    pub erode: f32,
+    pub deposit: f32,
+    pub evaporation: f32,
+    pub radius: i32,
+    pub thermal_steps: usize,
+    pub talus: f32,
+    pub thermal_rate: f32,
+}
+
+impl Default for ErosionConfig {
+    fn default() -> Self {
+        Self {
+            droplets: 1.0,
+            lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            erode: 0.3,
+            deposit: 0.3,
+            evaporation: 0.02,
+            radius: 3,
+            thermal_steps: 20,
+            talus: 1.2,
+            thermal_rate: 0.5,
+        }
+    }
+}
+
+// Erodes a heightfield of width columns along x, stored by z, then x.
+pub fn erode<R: Rng>(heights: &mut [f32], width: usize, config: &ErosionConfig, rng: &mut R) {
+    hydraulic(heights, width, config, rng);
+    thermal(heights, width, config);
+}
+
+// The height at a position between columns and the slope there, interpolated bilinearly.
+fn sample(heights: &[f32], width: usize, pos: Vector2<f32>) -> (f32, Vector2<f32>) {
+    let (x, z) = (pos.x as usize, pos.y as usize);
+    let (u, v) = (pos.x.fract(), pos.y.fract());
+    let index = z * width + x;
+    let h00 = heights[index];
+    let h10 = heights[index + 1];
+    let h01 = heights[index + width];
+    let h11 = heights[index + width + 1];
+
+    let height =
+        h00 * (1.0 - u) * (1.0 - v) + h10 * u * (1.0 - v) + h01 * (1.0 - u) * v + h11 * u * v;
+    let gradient = Vector2::new(
+        (h10 - h00) * (1.0 - v) + (h11 - h01) * v,
+        (h01 - h00) * (1.0 - u) + (h11 - h10) * u,
+    );
+    (height, gradient)
+}
+
+fn hydraulic<R: Rng>(heights: &mut [f32], width: usize, config: &ErosionConfig, rng: &mut R) {
+    let depth = heights.len() / width;
+    if width < 2 || depth < 2 {
+        return;
+    }
+
+    // Columns within radius, weighted by how close they are
+    let mut brush = Vec::new();
+    for z in -config.radius..=config.radius {
+        for x in -config.radius..=config.radius {
+            let weight = config.radius as f32 + 0.5 - ((x * x + z * z) as f32).sqrt();
+            if weight > 0.0 {
+                brush.push((x, z, weight));
+            }
+        }
+    }
+    let total: f32 = brush.iter().map(|(_, _, weight)| weight).sum();
+
+    let max = Vector2::new((width - 1) as f32, (depth - 1) as f32);
+    let droplets = (config.droplets * (width * depth) as f32).round() as usize;
+    for _ in 0..droplets {
+        let mut pos = Vector2::new(rng.gen_range(0.0..max.x), rng.gen_range(0.0..max.y));
+        let mut dir = Vector2::zeros();
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+
+        for _ in 0..config.lifetime {
+            let (height, gradient) = sample(heights, width, pos);
+            dir = dir * config.inertia - gradient * (1.0 - config.inertia);
+            if dir.norm_squared() == 0.0 {
+                let angle = rng.gen::<f32>() * 2.0 * PI;
+                dir = Vector2::new(angle.cos(), angle.sin());
+            }
+            dir.normalize_mut();
+
+            let old = pos;
+            pos += dir;
+            if pos.x < 0.0 || pos.y < 0.0 || pos.x >= max.x || pos.y >= max.y {
+                break;
+            }
+
+            let (new_height, _) = sample(heights, width, pos);
+            let delta = new_height - height;
+            let capacity = (-delta * speed * water * config.capacity).max(MIN_CAPACITY);
+
+            let (x, z) = (old.x as usize, old.y as usize);
+            if sediment > capacity || delta > 0.0 {
+                // Uphill the droplet fills the pit it leaves behind, at most up to the next height
+                let amount = if delta > 0.0 {
+                    delta.min(sediment)
+                } else {
+                    (sediment - capacity) * config.deposit
+                };
+                sediment -= amount;
+                deposit(heights, width, old, amount);
+            } else {
+                // Never dig deeper than the height difference, which would leave a pit
+                let amount = ((capacity - sediment) * config.erode).min(-delta);
+                for &(bx, bz, weight) in &brush {
+                    let (bx, bz) = (x as i32 + bx, z as i32 + bz);
+                    if bx < 0 || bz < 0 || bx >= width as i32 || bz >= depth as i32 {
+                        continue;
+                    }
+
+                    let index = bz as usize * width + bx as usize;
+                    let eroded = (amount * weight / total).min(heights[index].max(0.0));
+                    heights[index] -= eroded;
+                    sediment += eroded;
+                }
+            }
+
+            speed = (speed * speed - delta * GRAVITY).max(0.0).sqrt();
+            water *= 1.0 - config.evaporation;
+        }
+
+        // Droplets that ran off the edge take their sediment with them
+        if pos.x < max.x && pos.y < max.y && pos.x >= 0.0 && pos.y >= 0.0 {
+            deposit(heights, width, pos, sediment);
+        }
+    }
+}
+
+// Spreads sediment over the four columns around a position.
+fn deposit(heights: &mut [f32], width: usize, pos: Vector2<f32>, amount: f32) {
+    let index = pos.y as usize * width + pos.x as usize;
+    let (u, v) = (pos.x.fract(), pos.y.fract());
+    heights[index] += amount * (1.0 - u) * (1.0 - v);
+    heights[index + 1] += amount * u * (1.0 - v);
+    heights[index + width] += amount * (1.0 - u) * v;
+    heights[index + width + 1] += amount * u * v;
+}
+
+fn thermal(heights: &mut [f32], width: usize, config: &ErosionConfig) {
+    let depth = (heights.len() / width) as i32;
+    let width = width as i32;
+    let mut changes = vec![0.0; heights.len()];
+
+    for _ in 0..config.thermal_steps {
+        for z in 0..depth {
+            for x in 0..width {
+                let index = (z * width + x) as usize;
+                let height = heights[index];
+
+                // The neighbours that are lower than the talus allows, with how much lower
+                let mut excess = [(0, 0.0); 8];
+                let mut count = 0;
+                let mut total = 0.0;
+                let mut largest: f32 = 0.0;
+                for nz in -1..=1 {
+                    for nx in -1..=1 {
+                        let (ox, oz) = (x + nx, z + nz);
+                        if (nx == 0 && nz == 0) || ox < 0 || oz < 0 || ox >= width || oz >= depth {
+                            continue;
+                        }
+
+                        let other = (oz * width + ox) as usize;
+                        let distance = ((nx * nx + nz * nz) as f32).sqrt();
+                        let difference = height - heights[other] - config.talus * distance;
+                        if difference > 0.0 {
+                            excess[count] = (other, difference);
+                            count += 1;
+                            total += difference;
+                            largest = largest.max(difference);
+                        }
+                    }
+                }
+
+                // Half of the largest excess levels the steepest slope, spread by steepness
+                let amount = config.thermal_rate * largest / 2.0;
+                for &(other, difference) in &excess[..count] {
+                    let moved = amount * difference / total;
+                    changes[index] -= moved;
+                    changes[other] += moved;
+                }
+            }
+        }
+
+        for (height, change) in heights.iter_mut().zip(changes.iter_mut()) {
+            *height += *change;
+            *change = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const WIDTH: usize = 48;
+
+    // A slope with a bump in the middle, so droplets have somewhere to run and something to carve
+    fn heightfield() -> Vec<f32> {
+        (0..WIDTH * WIDTH)
+            .map(|i| {
+                let (x, z) = ((i % WIDTH) as f32, (i / WIDTH) as f32);
+                let bump = (-((x - 24.0).powi(2) + (z - 24.0).powi(2)) / 60.0).exp();
+                x * 0.5 + bump * 12.0
+            })
+            .collect()
+    }
+
+    fn eroded(seed: u64) -> Vec<f32> {
+        let mut heights = heightfield();
+        let mut rng = StdRng::seed_from_u64(seed);
+        erode(&mut heights, WIDTH, &ErosionConfig::default(), &mut rng);
+        heights
+    }
+
+    #[test]
+    fn same_seed_same_heightfield() {
+        let heights = eroded(3);
+        assert_ne!(heights, heightfield());
+        assert_eq!(heights, eroded(3));
+        assert_ne!(heights, eroded(4));
+    }
+}
@@ -0,0 +1,178 @@
+use std::f32::consts::PI;
+
+use nalgebra::{Point3, Vector2};
+
+use rand::Rng;
+
+use crate::components::tree::TreeParams;
+use crate::math::IVec3;
+use crate::volume::Volume;
+
+// Candidates tried around every point before it stops spawning new points.
+const ATTEMPTS: usize = 30;
+
+// Trees are scattered using Poisson-disk sampling, so no two trees are closer than spacing. Spots
+// that are steeper than max_slope (height difference per voxel), within margin of the edge of the
+// world or not covered by an opaque voxel are left empty. Every tree picks one of the kinds by
+// weight and is scaled by a random factor between 1 - variation and 1 + variation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForestConfig {
+    pub spacing: f32,
+    pub max_slope: f32,
+    pub margin: i32,
+    pub variation: f32,
+    pub kinds: Vec<(TreeParams, f32)>,
+}
+
+impl Default for ForestConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 24.0,
+            max_slope: 1.0,
+            margin: 4,
+            variation: 0.2,
+            kinds: vec![
+                (TreeParams::oak(), 1.0),
+                (TreeParams::birch(), 2.0),
+                (TreeParams::bush(), 3.0),
+            ],
+        }
+    }
+}
+
+// Returns the start of the trunk and the params of every tree of the forest.
+pub fn scatter<R: Rng>(
+    volume: &Volume,
+    config: &ForestConfig,
+    rng: &mut R,
+) -> Vec<(Point3<f32>, TreeParams)> {
+    let size = volume.size();
+    let total_weight: f32 = config.kinds.iter().map(|(_, weight)| weight).sum();
+    if total_weight <= 0.0 {
+        return Vec::new();
+    }
+
+    // The height of the highest voxel of every column, or None if there is nothing to stand on
+    let heights: Vec<_> = (0..size.z)
+        .flat_map(|z| (0..size.x).map(move |x| (x, z)))
+        .map(|(x, z)| {
+            (0..size.y)
+                .rev()
+                .map(|y| (y, volume.get(IVec3::new(x, y, z))))
+                .find(|(_, voxel)| !voxel.is_empty())
+                .filter(|(_, voxel)| voxel.transparency() == 15)
+                .map(|(y, _)| y)
+        })
+        .collect();
+    let height = |x: i32, z: i32| {
+        let (x, z) = (x.max(0).min(size.x - 1), z.max(0).min(size.z - 1));
+        heights[(z * size.x + x) as usize]
+    };
+
+    let margin = config.margin as f32;
+    let min = Vector2::new(margin, margin);
+    let max = Vector2::new(size.x as f32 - margin, size.z as f32 - margin);
+
+    let mut trees = Vec::new();
+    for point in poisson_disk(min, max, config.spacing, rng) {
+        let (x, z) = (point.x as i32, point.y as i32);
+        let y = match height(x, z) {
+            Some(y) => y,
+            None => continue,
+        };
+
+        // Central differences, where missing neighbours count as level
+        let slope_x = (height(x + 1, z).unwrap_or(y) - height(x - 1, z).unwrap_or(y)) as f32;
+        let slope_z = (height(x, z + 1).unwrap_or(y) - height(x, z - 1).unwrap_or(y)) as f32;
+        if Vector2::new(slope_x, slope_z).norm() / 2.0 > config.max_slope {
+            continue;
+        }
+
+        let mut choice = rng.gen::<f32>() * total_weight;
+        let (params, _) = config
+            .kinds
+            .iter()
+            .find(|(_, weight)| {
+                choice -= weight;
+                choice < 0.0
+            })
+            .unwrap_or(&config.kinds[config.kinds.len() - 1]);
+
+        let factor = 1.0 + rng.gen_range(-1.0..=1.0) * config.variation;
+        trees.push((Point3::new(x as f32, y as f32, z as f32), params.scaled(factor)));
+    }
+
+    trees
+}
+
+// Bridson's algorithm: every new point is placed between spacing and 2 * spacing away from a
+// random active point, checking a background grid with cells small enough to hold one point each.
+fn poisson_disk<R: Rng>(
+    min: Vector2<f32>,
+    max: Vector2<f32>,
+    spacing: f32,
+    rng: &mut R,
+) -> Vec<Vector2<f32>> {
+    let size = max - min;
+    if size.x <= 0.0 || size.y <= 0.0 || spacing <= 0.0 {
+        return Vec::new();
+    }
+
+    let cell = spacing / 2f32.sqrt();
+    let width = (size.x / cell).ceil() as usize;
+    let height = (size.y / cell).ceil() as usize;
+    let cell_of = |point: Vector2<f32>| {
+        let cell = (point - min) / cell;
+        (cell.x as usize, cell.y as usize)
+    };
+
+    let mut grid: Vec<Option<usize>> = vec![None; width * height];
+    let mut points = vec![Vector2::new(
+        rng.gen_range(min.x..max.x),
+        rng.gen_range(min.y..max.y),
+    )];
+    let mut active = vec![0];
+    let (x, y) = cell_of(points[0]);
+    grid[y * width + x] = Some(0);
+
+    while !active.is_empty() {
+        let i = rng.gen_range(0..active.len());
+        let center = points[active[i]];
+
+        let candidate = (0..ATTEMPTS)
+            .map(|_| {
+                let angle = rng.gen::<f32>() * 2.0 * PI;
+                let radius = rng.gen_range(spacing..2.0 * spacing);
+                center + Vector2::new(angle.cos(), angle.sin()) * radius
+            })
+            .find(|&candidate| {
+                if candidate.x < min.x
+                    || candidate.y < min.y
+                    || candidate.x >= max.x
+                    || candidate.y >= max.y
+                {
+                    return false;
+                }
+
+                let (x, y) = cell_of(candidate);
+                let xs = x.saturating_sub(2)..(x + 3).min(width);
+                xs.flat_map(|x| (y.saturating_sub(2)..(y + 3).min(height)).map(move |y| (x, y)))
+                    .filter_map(|(x, y)| grid[y * width + x])
+                    .all(|point| (points[point] - candidate).norm() >= spacing)
+            });
+
+        match candidate {
+            Some(candidate) => {
+                let (x, y) = cell_of(candidate);
+                grid[y * width + x] = Some(points.len());
+                active.push(points.len());
+                points.push(candidate);
+            }
+            None => {
+                active.swap_remove(i);
+            }
+        }
+    }
+
+    points
+}
@@ -0,0 +1,4 @@
+pub mod caves;
+pub mod erosion;
+pub mod forest;
+pub mod terrain;
\ No newline at end of file
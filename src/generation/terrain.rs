@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
+
+use rand::Rng;
+
+use crate::components::tree::TreeParams;
+use crate::generation::caves::CaveConfig;
+use crate::generation::erosion::{self, ErosionConfig};
+use crate::generation::forest::ForestConfig;
+use crate::math::IVec3;
+use crate::volume::Volume;
+use crate::voxel::Voxel;
+
+// A layer of material below the surface. The colour of every voxel is darkened by up to variation
+// using a detail noise. The last layer fills everything below the layers above it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layer {
+    pub depth: i32,
+    pub material: Voxel,
+    pub variation: f64,
+}
+
+// A voxel is solid where the noise plus the height curve is above 0. The height curve maps the
+// height as a fraction of the world height to a bias and is linearly interpolated between points.
+// Without a seed the noise is seeded from the random number generator passed to generate. The
+// surface is eroded afterwards, using the same random number generator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainConfig {
+    pub seed: Option<u32>,
+    pub octaves: usize,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    pub detail_frequency: f64,
+    pub height_curve: Vec<(f64, f64)>,
+    pub layers: Vec<Layer>,
+    pub erosion: ErosionConfig,
+    pub caves: CaveConfig,
+    pub forest: ForestConfig,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            octaves: 6,
+            frequency: 0.002,
+            lacunarity: Fbm::DEFAULT_LACUNARITY,
+            persistence: 0.5,
+            detail_frequency: 1.0,
+            height_curve: vec![(0.0, 0.16), (1.0, -0.48)],
+            layers: vec![
+                Layer {
+                    depth: 1,
+                    material: Voxel::solid(0b01000010, 0b01111100, 0b00111010),
+                    variation: 1.0,
+                },
+                Layer {
+                    depth: 4,
+                    material: Voxel::solid(0b01001010, 0b01000010, 0b00110010),
+                    variation: 0.5,
+                },
+                Layer {
+                    depth: 0,
+                    material: Voxel::solid(0b01000010, 0b00111100, 0b00110010),
+                    variation: 0.1,
+                },
+            ],
+            erosion: ErosionConfig::default(),
+            caves: CaveConfig::default(),
+            forest: ForestConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Invalid(usize, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "{}", error),
+            ConfigError::Invalid(line, message) => write!(f, "Line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+fn parse<T: std::str::FromStr>(line: usize, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::Invalid(line, format!("Invalid value '{}'", value)))
+}
+
+impl TerrainConfig {
+    // Reads a config from lines of `key = value`, where lines starting with # are comments.
+    // Missing keys keep their default. Height curves are given as `height:bias` pairs and layers
+    // as `depth r g b variation`, with one `layer` line per layer from the surface down. Keys of
+    // hydraulic erosion start with `erosion_`, keys of thermal erosion with `thermal_`, keys of
+    // the cave pass with `cave_` and keys of the forest with `forest_`, with one
+    // `forest_tree = preset weight` line per kind of tree.
+    pub fn from_file<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut config = Self::default();
+        let mut layers = Vec::new();
+        let mut kinds = Vec::new();
+
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=').map(str::trim);
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next().ok_or_else(|| {
+                ConfigError::Invalid(line_number, "Expected key = value".to_string())
+            })?;
+
+            match key {
+                "seed" => config.seed = Some(parse(line_number, value)?),
+                "octaves" => config.octaves = parse(line_number, value)?,
+                "frequency" => config.frequency = parse(line_number, value)?,
+                "lacunarity" => config.lacunarity = parse(line_number, value)?,
+                "persistence" => config.persistence = parse(line_number, value)?,
+                "detail_frequency" => config.detail_frequency = parse(line_number, value)?,
+                "erosion_droplets" => config.erosion.droplets = parse(line_number, value)?,
+                "erosion_lifetime" => config.erosion.lifetime = parse(line_number, value)?,
+                "erosion_inertia" => config.erosion.inertia = parse(line_number, value)?,
+                "erosion_capacity" => config.erosion.capacity = parse(line_number, value)?,
+                "erosion_erode" => config.erosion.erode = parse(line_number, value)?,
+                "erosion_deposit" => config.erosion.deposit = parse(line_number, value)?,
+                "erosion_evaporation" => config.erosion.evaporation = parse(line_number, value)?,
+                "erosion_radius" => config.erosion.radius = parse(line_number, value)?,
+                "thermal_steps" => config.erosion.thermal_steps = parse(line_number, value)?,
+                "thermal_talus" => config.erosion.talus = parse(line_number, value)?,
+                "thermal_rate" => config.erosion.thermal_rate = parse(line_number, value)?,
+                "cave_worms" => config.caves.worms = parse(line_number, value)?,
+                "cave_worm_length" => config.caves.worm_length = parse(line_number, value)?,
+                "cave_worm_radius" => {
+                    let mut values = value.split_whitespace();
+                    let min = parse(line_number, values.next().unwrap_or_default())?;
+                    let max = parse(line_number, values.next().unwrap_or(value))?;
+                    if min > max {
+                        return Err(ConfigError::Invalid(
+                            line_number,
+                            "Expected cave_worm_radius = min max".to_string(),
+                        ));
+                    }
+                    config.caves.worm_radius = (min, max);
+                }
+                "cave_frequency" => config.caves.cavern_frequency = parse(line_number, value)?,
+                "cave_density" => config.caves.cavern_density = parse(line_number, value)?,
+                "cave_min_depth" => config.caves.min_depth = parse(line_number, value)?,
+                "forest_spacing" => config.forest.spacing = parse(line_number, value)?,
+                "forest_max_slope" => config.forest.max_slope = parse(line_number, value)?,
+                "forest_margin" => config.forest.margin = parse(line_number, value)?,
+                "forest_variation" => config.forest.variation = parse(line_number, value)?,
+                "forest_tree" => {
+                    let values: Vec<_> = value.split_whitespace().collect();
+                    if values.len() != 2 {
+                        return Err(ConfigError::Invalid(
+                            line_number,
+                            "Expected forest_tree = preset weight".to_string(),
+                        ));
+                    }
+
+                    let params = TreeParams::preset(values[0]).ok_or_else(|| {
+                        ConfigError::Invalid(
+                            line_number,
+                            format!("Unknown tree preset '{}'", values[0]),
+                        )
+                    })?;
+                    kinds.push((params, parse(line_number, values[1])?));
+                }
+                "height_curve" => {
+                    let mut curve = Vec::new();
+                    for point in value.split_whitespace() {
+                        let mut point = point.splitn(2, ':');
+                        let height = parse(line_number, point.next().unwrap_or_default())?;
+                        let bias = parse(line_number, point.next().unwrap_or_default())?;
+                        curve.push((height, bias));
+                    }
+
+                    if curve.is_empty() {
+                        return Err(ConfigError::Invalid(
+                            line_number,
+                            "Height curve needs at least one point".to_string(),
+                        ));
+                    }
+                    curve.sort_by(|a: &(f64, f64), b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+                    config.height_curve = curve;
+                }
+                "layer" => {
+                    let values: Vec<_> = value.split_whitespace().collect();
+                    if values.len() != 5 {
+                        return Err(ConfigError::Invalid(
+                            line_number,
+                            "Expected layer = depth r g b variation".to_string(),
+                        ));
+                    }
+
+                    layers.push(Layer {
+                        depth: parse(line_number, values[0])?,
+                        material: Voxel::solid(
+                            parse(line_number, values[1])?,
+                            parse(line_number, values[2])?,
+                            parse(line_number, values[3])?,
+                        ),
+                        variation: parse(line_number, values[4])?,
+                    });
+                }
+                _ => {
+                    return Err(ConfigError::Invalid(
+                        line_number,
+                        format!("Unknown key '{}'", key),
+                    ))
+                }
+            }
+        }
+
+        if !layers.is_empty() {
+            config.layers = layers;
+        }
+
+        if !kinds.is_empty() {
+            config.forest.kinds = kinds;
+        }
+
+        Ok(config)
+    }
+
+    fn height_bias(&self, height: f64) -> f64 {
+        let curve = &self.height_curve;
+        let first = curve[0];
+        let last = curve[curve.len() - 1];
+        if height <= first.0 {
+            return first.1;
+        }
+
+        for window in curve.windows(2) {
+            let (from, to) = (window[0], window[1]);
+            if height <= to.0 {
+                let t = (height - from.0) / (to.0 - from.0).max(f64::EPSILON);
+                return from.1 + (to.1 - from.1) * t;
+            }
+        }
+
+        last.1
+    }
+
+    fn layer(&self, depth: i32) -> &Layer {
+        let mut bottom = 0;
+        for layer in &self.layers[..self.layers.len() - 1] {
+            bottom += layer.depth;
+            if depth < bottom {
+                return layer;
+            }
+        }
+
+        &self.layers[self.layers.len() - 1]
+    }
+}
+
+fn to_color(material: Voxel, factor: f64) -> Voxel {
+    let (r, g, b) = material.rgb();
+    material.with_rgb(
+        (r as f64 * factor) as u8,
+        (g as f64 * factor) as u8,
+        (b as f64 * factor) as u8,
+    )
+}
+
+// Evaluates the noise for the whole volume first, so the surface can be eroded as a heightfield
+// before the volume is filled. Every column is then filled from the top down, tracking the depth
+// below the surface to pick the layer of each voxel. Eroded voxels are left empty and sediment
+// fills up the column above the old surface, while the noise still decides below it.
+pub fn generate<R: Rng>(volume: &mut Volume, config: &TerrainConfig, rng: &mut R) {
+    assert!(!config.layers.is_empty(), "Terrain needs at least one layer");
+    assert!(!config.height_curve.is_empty(), "Terrain needs a height curve");
+
+    let size = volume.size();
+    let fbm = Fbm::new()
+        .set_seed(config.seed.unwrap_or_else(|| rng.gen()))
+        .set_octaves(config.octaves)
+        .set_frequency(config.frequency)
+        .set_lacunarity(config.lacunarity)
+        .set_persistence(config.persistence);
+    let detail = fbm.clone().set_frequency(config.detail_frequency);
+
+    let column = |x: i32, z: i32| ((z * size.x + x) * size.y) as usize;
+    let mut solid = vec![false; (size.x * size.y * size.z) as usize];
+    let mut surface = vec![-1; (size.x * size.z) as usize];
+    for z in 0..size.z {
+        for x in 0..size.x {
+            for y in 0..size.y {
+                let noise = fbm.get([x as f64, y as f64, z as f64]);
+                if noise + config.height_bias(y as f64 / size.y as f64) > 0.0 {
+                    solid[column(x, z) + y as usize] = true;
+                    surface[(z * size.x + x) as usize] = y;
+                }
+            }
+        }
+    }
+
+    let mut heights: Vec<_> = surface.iter().map(|&y| y as f32).collect();
+    erosion::erode(&mut heights, size.x as usize, &config.erosion, rng);
+
+    for z in 0..size.z {
+        for x in 0..size.x {
+            let surface = surface[(z * size.x + x) as usize];
+            let eroded = heights[(z * size.x + x) as usize].round() as i32;
+
+            let mut depth = 0;
+            for y in (0..size.y).rev() {
+                if y <= eroded && (y > surface || solid[column(x, z) + y as usize]) {
+                    let layer = config.layer(depth);
+                    let factor = detail.get([x as f64, (size.y - y - 1) as f64, z as f64]);
+                    let value = to_color(layer.material, 1.0 - factor * layer.variation);
+
+                    volume.set(IVec3::new(x, y, z), value);
+                    depth += 1;
+                } else {
+                    depth = 0;
+                }
+            }
+        }
+    }
+}
@@ -9,21 +9,47 @@ use winit::event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCo
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::Window;
 
+mod atlas;
 mod components;
 mod dispatcher;
+mod emitters;
+mod format;
+mod generation;
 mod math;
 mod misc;
+mod occupancy;
+mod octree;
+mod prefab;
 mod systems;
 mod volume;
+mod voxel;
 mod vulkan;
+mod water;
 mod window;
 
-use components::tree::Tree;
+use atlas::Atlas;
+use components::light::Light;
+use components::lsystem::{LSystem, LSystemTree};
+use components::tree::{SpaceColonization, Tree};
 use dispatcher::Dispatcher;
+use emitters::Emitters;
+use format::world::Compression;
+use generation::{caves, forest};
+use generation::terrain::{self, TerrainConfig};
 use math::matrices::Matrices;
-use misc::light::Light;
+use math::IVec3;
+use misc::ambient_occlusion::AmbientOcclusion;
+use misc::alpha_iors::AlphaIors;
+use misc::random::Random;
+use misc::specs::Specs;
+use misc::sun::{Sun, TimeOfDay};
+use occupancy::Occupancy;
+use octree::Octree;
+use prefab::{Merge, Prefab};
 use volume::*;
+use voxel::Voxel;
 use vulkan::Vulkan;
+use water::{Water, MAX_LEVEL};
 use window::{keyboard::Keyboard, mouse::Mouse};
 
 struct App {
@@ -32,63 +58,185 @@ struct App {
 
 impl App {
     pub fn new(window: Window) -> Self {
-        // let mut texture = Volume::from_file("assets/world", 512);
-        let mut texture = Volume::new(64);
+        let mut path = None;
+        let mut seed = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().and_then(|value| value.parse().ok());
+                    seed = Some(value.expect("Expected a number after --seed"));
+                }
+                _ => path = Some(arg),
+            }
+        }
 
-        let size = texture.size();
-        let mut height = 0;
+        let mut random = Random::new(seed.unwrap_or_else(rand::random));
+        println!("Seed: {}", random.seed());
+
+        let config = match &path {
+            Some(path) if path.ends_with(".terrain") => {
+                TerrainConfig::from_file(path).expect("Failed to load terrain config")
+            }
+            _ => TerrainConfig::default(),
+        };
 
-        for y in 0..size {
-            if texture.data[(size / 2) * size * size + y * size + size / 2] > 0 {
-                height = y;
+        let mut texture = match path {
+            Some(path) if path.ends_with(".vox") => {
+                Volume::from_vox_file(path).expect("Failed to import MagicaVoxel file")
+            }
+            Some(path) if !path.ends_with(".terrain") => {
+                Volume::from_file(path).expect("Failed to load world")
             }
+            _ => Self::generate_world(IVec3::new(128, 64, 128), &config, &mut random),
+        };
+
+        let size = texture.size();
+        let height = Self::surface(&texture, size.x / 2, size.z / 2);
+
+        let trees = Self::plant_forest(&mut texture, &config, &mut random);
+
+        let mut lsystem_trees = Vec::new();
+        for (path, x, z) in &[
+            ("assets/trees/conifer.lsystem", size.x / 2 - 15, size.z / 2 - 25),
+            ("assets/trees/palm.lsystem", size.x / 2 + 18, size.z / 2 - 22),
+        ] {
+            let lsystem = LSystem::from_file(path).expect("Failed to load L-system");
+            let start = Point3::new(*x as f32, Self::surface(&texture, *x, *z) as f32, *z as f32);
+            let tree = LSystemTree::new(&lsystem, start).expect("Failed to derive L-system");
+            lsystem_trees.push(tree);
         }
 
-        let start = Point3::new(size as f32 / 2.0, height as f32, size as f32 / 2.0);
-        let offset = Vector3::new(
-            rand::random::<f32>() * 10.0 - 5.0,
-            35.0 + rand::random::<f32>() * 10.0,
-            rand::random::<f32>() * 10.0 - 5.0,
-        );
-        let tree = Tree::new(start + offset, 30.0, 400, start, &mut texture.data, size);
-
-        let glass = 0b10000000_11111111_11111111_11111111;
-        let metal = 0b11111000_01010101_01010101_11111111;
-        for y in 0..15 {
-            for z in 0..15 {
-                texture.data
-                    [(size / 2 - 8 + z) * size * size + (height + 10 + y) * size + size / 2 - 30] =
-                    glass;
-                texture.data
-                    [(size / 2 - 8 + z) * size * size + (height + 10 + y) * size + size / 2 + 30] =
-                    metal;
+        // Two walls facing each other, the metal one mirrored to the other side. The metal wall
+        // only fills the air, so it never cuts into the terrain.
+        for (path, x, mirrored, merge) in &[
+            ("assets/prefabs/glass_wall.world", size.x / 2 - 30, false, Merge::SkipAir),
+            ("assets/prefabs/metal_wall.world", size.x / 2 + 30, true, Merge::IntoAir),
+        ] {
+            let mut wall = Prefab::from_file(path).expect("Failed to load prefab");
+            wall = wall.rotated(1);
+            if *mirrored {
+                wall = wall.mirrored();
             }
+
+            let pos = IVec3::new(*x, height + 10, size.z / 2 - 1);
+            wall.paste(&mut texture, pos, *merge);
+        }
+
+        // A floating lamp between the walls that lights up the ground below it, replacing anything
+        // that is in its way
+        let mut lamp = Volume::new(IVec3::repeat(2));
+        for pos in math::box_positions(IVec3::zeros(), IVec3::repeat(1)) {
+            lamp.set(pos, Voxel::emissive(255, 200, 120, 15));
         }
+        let (x, z) = (size.x / 2, size.z / 2 - 8);
+        let pos = IVec3::new(x, Self::surface(&texture, x, z) + 4, z);
+        Prefab::new(lamp, IVec3::zeros()).paste(&mut texture, pos, Merge::Overwrite);
+
+        // A block of water that falls down and spreads over the terrain
+        let mut water = Water::new(&texture);
+        let min = IVec3::new(size.x / 2 - 6, height + 15, size.z / 2 + 20);
+        for pos in math::box_positions(min, min.add_scalar(11)) {
+            water.add(&mut texture, pos, MAX_LEVEL);
+        }
+
+        let octree = Octree::new(&texture);
+        let occupancy = Occupancy::new(&texture);
+        let emitters = Emitters::new(&texture);
+        let alpha_iors = AlphaIors::default();
+        let mut atlas = Atlas::new(&texture);
+        let dirty = texture.take_dirty();
+        let regions = atlas.update(&texture, &dirty);
+        let atlas_size = atlas.size();
 
         let inv_proj = Self::create_inv_proj(window.inner_size());
         let view = Matrix4::identity();
 
         let mut dispatcher = Dispatcher::new();
-        let vulkan = Vulkan::builder(window)
+        let mut vulkan = Vulkan::builder(window)
             .with_uniform::<Matrices>(0, vk::ShaderStageFlags::VERTEX)
             // .with_texture(1, vk::ShaderStageFlags::FRAGMENT, &texture)
-            .with_dynamic_texture(1, vk::ShaderStageFlags::FRAGMENT, &texture)
-            .with_uniform::<u32>(2, vk::ShaderStageFlags::FRAGMENT)
-            .with_storage::<Light>(3, vk::ShaderStageFlags::FRAGMENT, 1)
+            .with_dynamic_texture(
+                1,
+                vk::ShaderStageFlags::FRAGMENT,
+                atlas_size.x as u32,
+                atlas_size.y as u32,
+                atlas_size.z as u32,
+            )
+            .with_uniform::<Specs>(2, vk::ShaderStageFlags::FRAGMENT)
+            .with_dynamic_storage::<Light>(3, vk::ShaderStageFlags::FRAGMENT, 1)
+            .with_dynamic_storage::<u32>(4, vk::ShaderStageFlags::FRAGMENT, octree.nodes.len())
+            .with_dynamic_storage::<u32>(5, vk::ShaderStageFlags::FRAGMENT, atlas.pages.len())
+            .with_dynamic_storage::<u32>(6, vk::ShaderStageFlags::FRAGMENT, occupancy.bits.len())
+            .with_uniform::<Sun>(7, vk::ShaderStageFlags::FRAGMENT)
+            .with_dynamic_storage::<u32>(8, vk::ShaderStageFlags::FRAGMENT, emitters.grid.len())
+            .with_dynamic_storage::<f32>(9, vk::ShaderStageFlags::FRAGMENT, alpha_iors.iors.len())
             .build();
 
-        vulkan.update_texture(1, &texture.data);
+        vulkan.update_texture_regions(1, &regions);
+        vulkan.update_storage(4, &octree.nodes);
+        vulkan.update_storage(5, &atlas.pages);
+        vulkan.update_storage(6, &occupancy.bits);
+        vulkan.update_storage(8, &emitters.grid);
+        vulkan.update_storage(9, &alpha_iors.iors);
         dispatcher.world_mut().insert(vulkan);
         dispatcher.world_mut().insert(Matrices { inv_proj, view });
         dispatcher.world_mut().insert(texture);
+        dispatcher.world_mut().insert(atlas);
+        dispatcher.world_mut().insert(octree);
+        dispatcher.world_mut().insert(occupancy);
+        dispatcher.world_mut().insert(emitters);
+        dispatcher.world_mut().insert(alpha_iors);
+        dispatcher.world_mut().insert(water);
+        dispatcher.world_mut().insert(random);
+        dispatcher.world_mut().insert(TimeOfDay::default());
+        dispatcher.world_mut().insert(AmbientOcclusion::default());
         dispatcher.world_mut().insert(Keyboard::default());
         dispatcher.world_mut().insert(Mouse::default());
 
-        dispatcher.world_mut().create_entity().with(tree).build();
+        for tree in trees {
+            dispatcher.world_mut().create_entity().with(Tree::new(tree)).build();
+        }
+        for tree in lsystem_trees {
+            dispatcher.world_mut().create_entity().with(Tree::new(tree)).build();
+        }
+
+        let light = Light::new(
+            Vector3::new(-25.0, -120.0, -5.0),
+            Vector4::new(1.0, 0.0, 1.0, 0.4),
+            20.0,
+            50.0,
+        );
+        dispatcher.world_mut().create_entity().with(light).build();
 
         Self { dispatcher }
     }
 
+    fn generate_world(size: IVec3, config: &TerrainConfig, random: &mut Random) -> Volume {
+        let mut volume = Volume::new(size);
+        terrain::generate(&mut volume, config, random);
+        caves::carve(&mut volume, &config.caves, random);
+        volume
+    }
+
+    fn plant_forest(
+        volume: &mut Volume,
+        config: &TerrainConfig,
+        random: &mut Random,
+    ) -> Vec<SpaceColonization> {
+        forest::scatter(volume, &config.forest, random)
+            .into_iter()
+            .map(|(start, params)| SpaceColonization::new(start, &params, volume, random))
+            .collect()
+    }
+
+    fn surface(volume: &Volume, x: i32, z: i32) -> i32 {
+        (0..volume.size().y)
+            .rev()
+            .find(|&y| !volume.get(IVec3::new(x, y, z)).is_empty())
+            .unwrap_or(0)
+    }
+
     fn create_inv_proj(size: PhysicalSize<u32>) -> Matrix4<f32> {
         Matrix4::new_perspective(
             size.width as f32 / size.height as f32,
@@ -120,6 +268,24 @@ impl App {
                         (Some(VirtualKeyCode::Escape), ElementState::Pressed) => {
                             *control_flow = ControlFlow::Exit
                         }
+                        (Some(VirtualKeyCode::F5), ElementState::Pressed) => {
+                            let texture = self.dispatcher.world().read_resource::<Volume>();
+                            if let Err(error) = texture.save("assets/world", Compression::RunLength)
+                            {
+                                eprintln!("Failed to save world: {}", error);
+                            }
+                        }
+                        (Some(VirtualKeyCode::F6), ElementState::Pressed) => {
+                            let texture = self.dispatcher.world().read_resource::<Volume>();
+                            if let Err(error) = texture.save_vox("assets/world.vox") {
+                                eprintln!("Failed to export world: {}", error);
+                            }
+                        }
+                        (Some(VirtualKeyCode::F7), ElementState::Pressed) => {
+                            let mut ambient_occlusion =
+                                self.dispatcher.world().write_resource::<AmbientOcclusion>();
+                            *ambient_occlusion = ambient_occlusion.next();
+                        }
                         (button, state) => {
                             let mut keyboard = self.dispatcher.world().write_resource::<Keyboard>();
                             keyboard.update_buttons(button, state);
@@ -150,14 +316,6 @@ impl App {
                 self.dispatcher.update();
                 let mut mouse = self.dispatcher.world().write_resource::<Mouse>();
                 mouse.update_delta((0.0, 0.0));
-                let mut vulkan = self.dispatcher.world().write_resource::<Vulkan>();
-                let light = Light::new(
-                    Vector3::new(-25.0, -120.0, -5.0),
-                    Vector4::new(1.0, 0.0, 1.0, 0.4),
-                    20.0,
-                    50.0,
-                );
-                vulkan.update_buffer(3, light);
             }
             _ => (),
         });
@@ -170,3 +328,27 @@ fn main() {
     let app = App::new(window);
     app.run(event_loop);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(seed: u64) -> Volume {
+        let config = TerrainConfig::default();
+        let mut random = Random::new(seed);
+        let mut volume = App::generate_world(IVec3::new(64, 64, 64), &config, &mut random);
+        assert!(!App::plant_forest(&mut volume, &config, &mut random).is_empty());
+        volume
+    }
+
+    #[test]
+    fn same_seed_same_world() {
+        let (a, b) = (generate(7), generate(7));
+        let max = a.size().add_scalar(-1);
+        assert_eq!(a.size(), b.size());
+        assert!(a
+            .iter_box(IVec3::zeros(), max)
+            .zip(b.iter_box(IVec3::zeros(), max))
+            .all(|(a, b)| a == b));
+    }
+}
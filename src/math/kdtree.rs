@@ -0,0 +1,157 @@
+use std::cmp::Ordering;
+
+use nalgebra::Point3;
+
+struct Node {
+    point: Point3<f32>,
+    value: usize,
+    axis: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+// A k-d tree over points, each with a value such as an index into another list. Points are
+// inserted at the leaves, which can leave the tree unbalanced when they arrive in order, so it is
+// rebuilt around the medians whenever it has doubled in size since the last rebuild.
+#[derive(Default)]
+pub struct KdTree {
+    nodes: Vec<Node>,
+    root: Option<usize>,
+    balanced: usize,
+}
+
+impl KdTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, point: Point3<f32>, value: usize) {
+        let index = self.nodes.len();
+        let mut parent = match self.root {
+            Some(root) => root,
+            None => {
+                self.nodes.push(Node {
+                    point,
+                    value,
+                    axis: 0,
+                    left: None,
+                    right: None,
+                });
+                self.root = Some(index);
+                self.balanced = 1;
+                return;
+            }
+        };
+
+        loop {
+            let node = &mut self.nodes[parent];
+            let child = if point[node.axis] < node.point[node.axis] {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+
+            match *child {
+                Some(next) => parent = next,
+                None => {
+                    *child = Some(index);
+                    let axis = (node.axis + 1) % 3;
+                    self.nodes.push(Node {
+                        point,
+                        value,
+                        axis,
+                        left: None,
+                        right: None,
+                    });
+                    break;
+                }
+            }
+        }
+
+        if self.nodes.len() >= self.balanced * 2 {
+            self.rebuild();
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let mut points: Vec<_> = self
+            .nodes
+            .drain(..)
+            .map(|node| (node.point, node.value))
+            .collect();
+        self.root = self.build(&mut points, 0);
+        self.balanced = self.nodes.len();
+    }
+
+    fn build(&mut self, points: &mut [(Point3<f32>, usize)], axis: usize) -> Option<usize> {
+        if points.is_empty() {
+            return None;
+        }
+
+        points.sort_by(|a, b| a.0[axis].partial_cmp(&b.0[axis]).unwrap_or(Ordering::Equal));
+        let mut median = points.len() / 2;
+        // Equal coordinates have to end up on the right, like they do when inserted
+        while median > 0 && points[median - 1].0[axis] == points[median].0[axis] {
+            median -= 1;
+        }
+
+        let (point, value) = points[median];
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            point,
+            value,
+            axis,
+            left: None,
+            right: None,
+        });
+
+        let (left, right) = points.split_at_mut(median);
+        let left = self.build(left, (axis + 1) % 3);
+        let right = self.build(&mut right[1..], (axis + 1) % 3);
+        self.nodes[index].left = left;
+        self.nodes[index].right = right;
+
+        Some(index)
+    }
+
+    // The value of the point closest to the given point together with its distance, only looking
+    // at points closer than max_dist. Of several points at the same distance, the one with the
+    // lowest value wins.
+    pub fn nearest(&self, point: &Point3<f32>, max_dist: f32) -> Option<(usize, f32)> {
+        let mut best = None;
+        let mut best_dist = max_dist * max_dist;
+        // Nodes to visit with the squared distance to the splitting plane that led to them
+        let mut stack: Vec<_> = self.root.map(|root| (root, 0.0)).into_iter().collect();
+
+        while let Some((index, bound)) = stack.pop() {
+            if bound > best_dist {
+                continue;
+            }
+
+            let node = &self.nodes[index];
+            let dist = (node.point - point).norm_squared();
+            if dist < best_dist
+                || (dist == best_dist && best.is_some_and(|value| node.value < value))
+            {
+                best = Some(node.value);
+                best_dist = dist;
+            }
+
+            // Visit the side of the point last, so it is popped first, and skip the other side
+            // when the splitting plane is further away than the best point so far
+            let offset = point[node.axis] - node.point[node.axis];
+            let (near, far) = if offset < 0.0 {
+                (node.left, node.right)
+            } else {
+                (node.right, node.left)
+            };
+
+            if let Some(far) = far.filter(|_| offset * offset <= best_dist) {
+                stack.push((far, offset * offset));
+            }
+            stack.extend(near.map(|near| (near, 0.0)));
+        }
+
+        best.map(|value| (value, best_dist.sqrt()))
+    }
+}
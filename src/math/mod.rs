@@ -1 +1,13 @@
-pub mod matrices;
\ No newline at end of file
+pub mod kdtree;
+pub mod matrices;
+
+use nalgebra::Vector3;
+
+pub type IVec3 = Vector3<i32>;
+
+// Every position in the inclusive box from min to max, ordered by x, then y, then z.
+pub fn box_positions(min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> {
+    (min.z..=max.z).flat_map(move |z| {
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| IVec3::new(x, y, z)))
+    })
+}
\ No newline at end of file
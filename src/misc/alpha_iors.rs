@@ -0,0 +1,39 @@
+use crate::voxel::Voxel;
+use crate::water::WATER;
+
+pub const GLASS_IOR: f32 = 1.5;
+pub const WATER_IOR: f32 = 1.33;
+
+// Indices of refraction per alpha byte, the transparency and reflectivity the shader reads as the
+// alpha of a voxel. Voxels don't store a material, so every voxel with the same alpha byte shares
+// an entry, regardless of its colour. Only transparent voxels refract, and all of them are glass
+// unless told otherwise.
+pub struct AlphaIors {
+    pub iors: Vec<f32>,
+    changed: bool,
+}
+
+impl Default for AlphaIors {
+    fn default() -> Self {
+        let mut alpha_iors = Self {
+            iors: vec![GLASS_IOR; 256],
+            changed: false,
+        };
+        alpha_iors.set_ior(WATER, WATER_IOR);
+        alpha_iors.changed = false;
+        alpha_iors
+    }
+}
+
+impl AlphaIors {
+    // Sets the index of refraction of every voxel with the same alpha byte as voxel.
+    pub fn set_ior(&mut self, voxel: Voxel, ior: f32) {
+        self.iors[(voxel.to_bits() >> 24) as usize] = ior;
+        self.changed = true;
+    }
+
+    // Returns whether the table changed since the last call and has to be uploaded again.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+}
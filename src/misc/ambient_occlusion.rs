@@ -0,0 +1,20 @@
+// How the fragment shader darkens creases and corners that little sky light reaches. Neighbours
+// only looks at the voxels around the face that was hit, like per-vertex occlusion in a mesher,
+// while hemisphere casts short rays from the hit and is slower but also covers larger shapes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AmbientOcclusion {
+    Off,
+    #[default]
+    Neighbours,
+    Hemisphere,
+}
+
+impl AmbientOcclusion {
+    pub fn next(self) -> Self {
+        match self {
+            AmbientOcclusion::Off => AmbientOcclusion::Neighbours,
+            AmbientOcclusion::Neighbours => AmbientOcclusion::Hemisphere,
+            AmbientOcclusion::Hemisphere => AmbientOcclusion::Off,
+        }
+    }
+}
@@ -1 +1,5 @@
-pub mod light;
\ No newline at end of file
+pub mod alpha_iors;
+pub mod ambient_occlusion;
+pub mod random;
+pub mod specs;
+pub mod sun;
\ No newline at end of file
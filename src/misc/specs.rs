@@ -0,0 +1,28 @@
+use nalgebra::Vector3;
+
+use super::ambient_occlusion::AmbientOcclusion;
+
+#[repr(C)]
+#[derive(Default)]
+pub struct Specs {
+    size: Vector3<i32>,
+    lights: u32,
+    origin: Vector3<i32>,
+    ambient_occlusion: u32,
+}
+
+impl Specs {
+    pub fn new(
+        size: Vector3<i32>,
+        origin: Vector3<i32>,
+        lights: u32,
+        ambient_occlusion: AmbientOcclusion,
+    ) -> Self {
+        Self {
+            size,
+            lights,
+            origin,
+            ambient_occlusion: ambient_occlusion as u32,
+        }
+    }
+}
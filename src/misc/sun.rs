@@ -0,0 +1,95 @@
+use nalgebra::Vector3;
+
+const NIGHT_SKY: [f32; 3] = [0.01, 0.015, 0.04];
+const TWILIGHT_SKY: [f32; 3] = [0.85, 0.45, 0.3];
+const DAY_SKY: [f32; 3] = [0.45, 0.65, 1.0];
+const SUNSET_COLOR: [f32; 3] = [1.0, 0.55, 0.3];
+
+// The sun as seen by the fragment shader, where dir points towards the sun. Softness is the angle
+// in radians of the cone shadow rays are spread over, and the ambient light is what the sky adds to
+// surfaces in the shade.
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sun {
+    pub dir: Vector3<f32>,
+    pub intensity: f32,
+    pub color: Vector3<f32>,
+    pub softness: f32,
+    pub sky: Vector3<f32>,
+    pub ambient: f32,
+}
+
+impl Default for Sun {
+    fn default() -> Self {
+        TimeOfDay::default().sun()
+    }
+}
+
+// The time of day in hours, which moves on by 24 hours every day_length seconds. The sun rises at
+// 6 in the direction of sunrise, an angle in degrees around the y axis from x towards z, reaches
+// max_elevation degrees above the horizon at 12 and sets at 18 on the opposite side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeOfDay {
+    pub hour: f32,
+    pub day_length: f32,
+    pub sunrise: f32,
+    pub max_elevation: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 10.0,
+            day_length: 240.0,
+            sunrise: 20.0,
+            max_elevation: 70.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn advance(&mut self, seconds: f32) {
+        if self.day_length > 0.0 {
+            self.hour = (self.hour + seconds / self.day_length * 24.0).rem_euclid(24.0);
+        }
+    }
+
+    pub fn sun(&self) -> Sun {
+        let (sin, cos) = self.sunrise.to_radians().sin_cos();
+        let rise = Vector3::new(cos, 0.0, sin);
+        let side = Vector3::new(-sin, 0.0, cos);
+        let elevation = self.max_elevation.to_radians();
+        let up = Vector3::y() * elevation.sin() + side * elevation.cos();
+
+        let angle = (self.hour - 6.0) / 12.0 * std::f32::consts::PI;
+        let dir = (rise * angle.cos() + up * angle.sin()).normalize();
+
+        // The height of the sun decides everything else, so dawn and dusk look the same
+        let height = dir.y;
+        let day = smoothstep(0.0, 0.4, height);
+        let sky = if height > 0.0 {
+            lerp(TWILIGHT_SKY.into(), DAY_SKY.into(), smoothstep(0.0, 0.25, height))
+        } else {
+            lerp(TWILIGHT_SKY.into(), NIGHT_SKY.into(), smoothstep(0.0, -0.2, height))
+        };
+
+        Sun {
+            dir,
+            intensity: smoothstep(-0.05, 0.1, height),
+            color: lerp(SUNSET_COLOR.into(), Vector3::repeat(1.0), day),
+            // Low suns are spread out more by the atmosphere, which softens their shadows
+            softness: 0.08 - 0.06 * day,
+            sky,
+            ambient: 0.1 + 0.4 * smoothstep(-0.2, 0.3, height),
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: Vector3<f32>, b: Vector3<f32>, t: f32) -> Vector3<f32> {
+    a + (b - a) * t
+}
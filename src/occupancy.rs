@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use crate::math::{self, IVec3};
+use crate::volume::{DirtyBox, Volume, CHUNK_SIZE};
+
+// A two level pyramid inside of every chunk marking which 4x4x4 and 16x16x16 cells contain at
+// least one voxel. Every chunk takes up WORDS u32s in page table order: 16 words with one bit per
+// fine cell followed by one word with one bit per coarse cell, both numbered in x, y, z order.
+pub const FINE: i32 = 4;
+pub const COARSE: i32 = 16;
+pub const WORDS: usize = 17;
+
+const FINE_CELLS: i32 = CHUNK_SIZE as i32 / FINE;
+const COARSE_CELLS: i32 = CHUNK_SIZE as i32 / COARSE;
+
+pub struct Occupancy {
+    chunks: IVec3,
+    pub bits: Vec<u32>,
+}
+
+impl Occupancy {
+    pub fn new(volume: &Volume) -> Self {
+        let chunks = volume.chunks_per_axis();
+        let mut occupancy = Self {
+            chunks,
+            bits: vec![0; chunks.iter().product::<i32>() as usize * WORDS],
+        };
+
+        for key in volume.chunks().map(|(key, _)| *key) {
+            let min = key * CHUNK_SIZE as i32;
+            occupancy.update_chunk(volume, key, min, min.add_scalar(CHUNK_SIZE as i32 - 1));
+        }
+
+        occupancy
+    }
+
+    // Recomputes the cells overlapping the dirty boxes, so edits cost time in proportion to their size.
+    pub fn update(&mut self, volume: &Volume, dirty: &HashMap<IVec3, DirtyBox>) {
+        for (key, dirty) in dirty {
+            self.update_chunk(volume, *key, dirty.min, dirty.max);
+        }
+    }
+
+    fn update_chunk(&mut self, volume: &Volume, key: IVec3, min: IVec3, max: IVec3) {
+        let chunks = self.chunks;
+        let offset = ((key.z * chunks.y + key.y) * chunks.x + key.x) as usize * WORDS;
+        let words = &mut self.bits[offset..offset + WORDS];
+        let chunk_min = key * CHUNK_SIZE as i32;
+
+        for cell in math::box_positions((min - chunk_min) / FINE, (max - chunk_min) / FINE) {
+            let cell_min = chunk_min + cell * FINE;
+            let occupied = volume
+                .iter_box(cell_min, cell_min.add_scalar(FINE - 1))
+                .any(|(_, voxel)| !voxel.is_empty());
+
+            let bit = ((cell.z * FINE_CELLS + cell.y) * FINE_CELLS + cell.x) as usize;
+            if occupied {
+                words[bit / 32] |= 1 << (bit % 32);
+            } else {
+                words[bit / 32] &= !(1 << (bit % 32));
+            }
+        }
+
+        let ratio = COARSE / FINE;
+        for cell in math::box_positions((min - chunk_min) / COARSE, (max - chunk_min) / COARSE) {
+            let occupied = math::box_positions(cell * ratio, (cell * ratio).add_scalar(ratio - 1))
+                .any(|fine| {
+                    let bit = ((fine.z * FINE_CELLS + fine.y) * FINE_CELLS + fine.x) as usize;
+                    words[bit / 32] & (1 << (bit % 32)) != 0
+                });
+
+            let bit = (cell.z * COARSE_CELLS + cell.y) * COARSE_CELLS + cell.x;
+            if occupied {
+                words[WORDS - 1] |= 1 << bit;
+            } else {
+                words[WORDS - 1] &= !(1 << bit);
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::math::IVec3;
+use crate::volume::{Volume, CHUNK_SIZE};
+
+// Nodes are stored as a flat list of u32s with the root at index 0. A node is either EMPTY,
+// OCCUPIED (every chunk inside it is allocated) or the index of its first child, with all eight
+// children stored next to each other in x, y, z order. The octree stops at chunks, the inside of
+// a chunk is described by the occupancy pyramid.
+pub const EMPTY: u32 = 0;
+pub const OCCUPIED: u32 = 1 << 31;
+
+pub struct Octree {
+    pub nodes: Vec<u32>,
+}
+
+impl Octree {
+    pub fn new(volume: &Volume) -> Self {
+        let mut octree = Self { nodes: Vec::new() };
+
+        octree.update(volume);
+        octree
+    }
+
+    pub fn update(&mut self, volume: &Volume) {
+        self.nodes.clear();
+        self.nodes.push(EMPTY);
+
+        let size = volume.size().max() as usize;
+        let root = self.build(volume, 0, 0, 0, size.next_power_of_two().max(CHUNK_SIZE));
+        self.nodes[0] = root;
+    }
+
+    fn build(&mut self, volume: &Volume, x: usize, y: usize, z: usize, size: usize) -> u32 {
+        let pos = IVec3::new(x as i32, y as i32, z as i32);
+        if !volume.contains(pos) {
+            return EMPTY;
+        }
+
+        if size == CHUNK_SIZE {
+            return match volume.chunk(&(pos / CHUNK_SIZE as i32)) {
+                Some(_) => OCCUPIED,
+                None => EMPTY,
+            };
+        }
+
+        let half = size / 2;
+        let mut children = [EMPTY; 8];
+        for (i, child) in children.iter_mut().enumerate() {
+            *child = self.build(
+                volume,
+                x + (i & 1) * half,
+                y + (i >> 1 & 1) * half,
+                z + (i >> 2 & 1) * half,
+                half,
+            );
+        }
+
+        if children.iter().all(|&child| child == EMPTY) {
+            EMPTY
+        } else if children.iter().all(|&child| child == OCCUPIED) {
+            OCCUPIED
+        } else {
+            let index = self.nodes.len() as u32;
+            self.nodes.extend_from_slice(&children);
+            index
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use nalgebra::Matrix3;
+
+use crate::format::vox::VoxError;
+use crate::format::world::WorldError;
+use crate::math::IVec3;
+use crate::volume::Volume;
+
+// How the voxels of a prefab are combined with the voxels already in the world.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Merge {
+    // Every voxel is replaced, including with the empty voxels of the prefab
+    Overwrite,
+    // Only empty voxels of the world are filled
+    IntoAir,
+    // Empty voxels of the prefab leave the world as it is
+    SkipAir,
+}
+
+#[derive(Debug)]
+pub enum PrefabError {
+    World(WorldError),
+    Vox(VoxError),
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefabError::World(error) => write!(f, "{}", error),
+            PrefabError::Vox(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for PrefabError {}
+
+impl From<WorldError> for PrefabError {
+    fn from(error: WorldError) -> Self {
+        PrefabError::World(error)
+    }
+}
+
+impl From<VoxError> for PrefabError {
+    fn from(error: VoxError) -> Self {
+        PrefabError::Vox(error)
+    }
+}
+
+// A small volume that can be pasted into the world any number of times. The anchor is the voxel of
+// the prefab that ends up at the position it is pasted at, and the prefab is rotated and mirrored
+// around it.
+pub struct Prefab {
+    volume: Volume,
+    anchor: IVec3,
+    transform: Matrix3<i32>,
+}
+
+impl Prefab {
+    pub fn new(volume: Volume, anchor: IVec3) -> Self {
+        Self {
+            volume,
+            anchor,
+            transform: Matrix3::identity(),
+        }
+    }
+
+    // Loads a world file, or a MagicaVoxel file if the path ends with .vox. The anchor is the
+    // voxel at the world position 0 according to the origin of the file, which is the centre for
+    // files without one.
+    pub fn from_file<P>(path: P) -> Result<Self, PrefabError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let volume = match path.extension() {
+            Some(extension) if extension == "vox" => Volume::from_vox_file(path)?,
+            _ => Volume::from_file(path)?,
+        };
+
+        let anchor = -volume.origin();
+        Ok(Self::new(volume, anchor))
+    }
+
+    // Turns the prefab by quarter turns around the y axis, counterclockwise when seen from above.
+    pub fn rotated(mut self, quarter_turns: i32) -> Self {
+        let rotation = Matrix3::new(0, 0, 1, 0, 1, 0, -1, 0, 0);
+        for _ in 0..quarter_turns.rem_euclid(4) {
+            self.transform = rotation * self.transform;
+        }
+        self
+    }
+
+    // Mirrors the prefab along the x axis. Mirroring along z is the same as mirroring and turning
+    // it twice.
+    pub fn mirrored(mut self) -> Self {
+        self.transform = Matrix3::new(-1, 0, 0, 0, 1, 0, 0, 0, 1) * self.transform;
+        self
+    }
+
+    pub fn paste(&self, volume: &mut Volume, pos: IVec3, merge: Merge) {
+        let size = self.volume.size();
+        for (local, voxel) in self.volume.iter_box(IVec3::zeros(), size.add_scalar(-1)) {
+            let target = pos + self.transform * (local - self.anchor);
+            let keep = match merge {
+                Merge::Overwrite => false,
+                Merge::IntoAir => !volume.get(target).is_empty(),
+                Merge::SkipAir => voxel.is_empty(),
+            };
+
+            if !keep {
+                volume.set(target, voxel);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::Voxel;
+
+    const A: Voxel = Voxel::solid(255, 0, 0);
+    const B: Voxel = Voxel::solid(0, 255, 0);
+    const C: Voxel = Voxel::solid(0, 0, 255);
+    const STONE: Voxel = Voxel::solid(128, 128, 128);
+
+    // Where every prefab is pasted
+    fn pos() -> IVec3 {
+        IVec3::repeat(8)
+    }
+
+    // An L of three voxels with an empty corner, anchored at A:
+    //
+    //   z=1  . C
+    //   z=0  A B
+    fn prefab() -> Prefab {
+        let mut volume = Volume::new(IVec3::new(2, 1, 2));
+        volume.set(IVec3::new(0, 0, 0), A);
+        volume.set(IVec3::new(1, 0, 0), B);
+        volume.set(IVec3::new(1, 0, 1), C);
+        Prefab::new(volume, IVec3::zeros())
+    }
+
+    // Where a voxel of the prefab ends up, turning (x, z) into (z, -x) for every quarter turn
+    fn place(local: IVec3, quarter_turns: i32, mirrored: bool) -> IVec3 {
+        let mut offset = local;
+        for _ in 0..quarter_turns {
+            offset = IVec3::new(offset.z, offset.y, -offset.x);
+        }
+        if mirrored {
+            offset.x = -offset.x;
+        }
+        pos() + offset
+    }
+
+    fn world() -> Volume {
+        let mut volume = Volume::new(IVec3::new(16, 16, 16));
+        for pos in crate::math::box_positions(pos().add_scalar(-1), pos().add_scalar(1)) {
+            volume.set(pos, STONE);
+        }
+        volume
+    }
+
+    #[test]
+    fn transforms() {
+        let cells = [
+            (IVec3::new(0, 0, 0), A),
+            (IVec3::new(1, 0, 0), B),
+            (IVec3::new(1, 0, 1), C),
+            (IVec3::new(0, 0, 1), Voxel::EMPTY),
+        ];
+
+        for quarter_turns in 0..4 {
+            for &mirrored in &[false, true] {
+                let mut prefab = prefab().rotated(quarter_turns);
+                if mirrored {
+                    prefab = prefab.mirrored();
+                }
+
+                let mut volume = Volume::new(IVec3::new(16, 16, 16));
+                prefab.paste(&mut volume, pos(), Merge::Overwrite);
+                for &(local, voxel) in &cells {
+                    let target = place(local, quarter_turns, mirrored);
+                    assert_eq!(volume.get(target), voxel, "{:?}", (quarter_turns, mirrored));
+                }
+            }
+        }
+
+        // A quarter turn is counterclockwise seen from above, so +x turns into -z
+        let mut volume = Volume::new(IVec3::new(16, 16, 16));
+        prefab().rotated(1).paste(&mut volume, pos(), Merge::SkipAir);
+        assert_eq!(volume.get(pos() + IVec3::new(0, 0, -1)), B);
+        assert_eq!(volume.get(pos() + IVec3::new(1, 0, -1)), C);
+    }
+
+    #[test]
+    fn merges() {
+        for quarter_turns in 0..4 {
+            for &mirrored in &[false, true] {
+                let mut prefab = prefab().rotated(quarter_turns);
+                if mirrored {
+                    prefab = prefab.mirrored();
+                }
+                let empty = place(IVec3::new(0, 0, 1), quarter_turns, mirrored);
+                let b = place(IVec3::new(1, 0, 0), quarter_turns, mirrored);
+
+                let mut volume = world();
+                prefab.paste(&mut volume, pos(), Merge::Overwrite);
+                assert_eq!(volume.get(empty), Voxel::EMPTY);
+                assert_eq!(volume.get(b), B);
+
+                let mut volume = world();
+                prefab.paste(&mut volume, pos(), Merge::IntoAir);
+                assert_eq!(volume.get(empty), STONE);
+                assert_eq!(volume.get(b), STONE);
+
+                let mut volume = world();
+                prefab.paste(&mut volume, pos(), Merge::SkipAir);
+                assert_eq!(volume.get(empty), STONE);
+                assert_eq!(volume.get(b), B);
+            }
+        }
+
+        // Air in the world is still filled when merging into air
+        let mut volume = Volume::new(IVec3::new(16, 16, 16));
+        prefab().paste(&mut volume, pos(), Merge::IntoAir);
+        assert_eq!(volume.get(pos()), A);
+    }
+}
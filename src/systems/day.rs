@@ -0,0 +1,30 @@
+use std::time::Instant;
+
+use specs::{System, Write};
+
+use crate::misc::sun::{Sun, TimeOfDay};
+
+// Moves the time of day on by the time since the previous frame and places the sun accordingly.
+pub struct DaySystem {
+    last: Instant,
+}
+
+impl DaySystem {
+    pub fn new() -> Self {
+        Self {
+            last: Instant::now(),
+        }
+    }
+}
+
+impl<'a> System<'a> for DaySystem {
+    type SystemData = (Write<'a, TimeOfDay>, Write<'a, Sun>);
+
+    fn run(&mut self, (mut time, mut sun): Self::SystemData) {
+        let now = Instant::now();
+        time.advance((now - self.last).as_secs_f32());
+        self.last = now;
+
+        *sun = time.sun();
+    }
+}
@@ -0,0 +1,32 @@
+use specs::prelude::*;
+use specs::shrev::ReaderId;
+use specs::storage::ComponentEvent;
+
+use crate::components::light::Light;
+use crate::vulkan::Vulkan;
+
+// Gathers every light into the light buffer, which grows when there are more lights than fit. The
+// lights are only uploaded again when one of them was added, changed or removed.
+#[derive(Default)]
+pub struct LightSystem {
+    reader: Option<ReaderId<ComponentEvent>>,
+}
+
+impl<'a> System<'a> for LightSystem {
+    type SystemData = (WriteExpect<'a, Vulkan>, ReadStorage<'a, Light>);
+
+    fn run(&mut self, (mut vulkan, lights): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("LightSystem was not set up");
+        if lights.channel().read(reader).count() == 0 {
+            return;
+        }
+
+        let lights: Vec<_> = lights.join().copied().collect();
+        vulkan.update_storage(3, &lights);
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.reader = Some(WriteStorage::<Light>::fetch(world).register_reader());
+    }
+}
@@ -1,7 +1,15 @@
 pub mod camera;
+pub mod day;
+pub mod light;
 pub mod render;
 pub mod tree;
+pub mod upload;
+pub mod water;
 
 pub use camera::CameraSystem;
+pub use day::DaySystem;
+pub use light::LightSystem;
 pub use render::RenderSystem;
-pub use tree::TreeSystem;
\ No newline at end of file
+pub use tree::TreeSystem;
+pub use upload::UploadSystem;
+pub use water::WaterSystem;
\ No newline at end of file
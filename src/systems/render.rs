@@ -1,6 +1,10 @@
-use specs::{Read, ReadExpect, System, WriteExpect};
+use specs::{Join, Read, ReadExpect, ReadStorage, System, WriteExpect};
 
+use crate::components::light::Light;
 use crate::math::matrices::Matrices;
+use crate::misc::ambient_occlusion::AmbientOcclusion;
+use crate::misc::specs::Specs;
+use crate::misc::sun::Sun;
 use crate::volume::Volume;
 use crate::vulkan::Vulkan;
 
@@ -10,14 +14,26 @@ impl<'a> System<'a> for RenderSystem {
     type SystemData = (
         WriteExpect<'a, Vulkan>,
         Read<'a, Matrices>,
+        Read<'a, Sun>,
+        Read<'a, AmbientOcclusion>,
         ReadExpect<'a, Volume>,
+        ReadStorage<'a, Light>,
     );
 
-    fn run(&mut self, (mut vulkan, matrices, texture): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut vulkan, matrices, sun, ambient_occlusion, texture, lights): Self::SystemData,
+    ) {
         let matrices = matrices.clone();
+        let sun = sun.clone();
+        let lights = lights.join().count() as u32;
         vulkan.begin_draw();
         vulkan.update_buffer(0, matrices);
-        vulkan.update_buffer(2, texture.size() as u32);
+        vulkan.update_buffer(
+            2,
+            Specs::new(texture.size(), texture.origin(), lights, *ambient_occlusion),
+        );
+        vulkan.update_buffer(7, sun);
         vulkan.end_draw();
     }
 }
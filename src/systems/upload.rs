@@ -0,0 +1,66 @@
+use specs::{System, WriteExpect};
+
+use crate::atlas::Atlas;
+use crate::emitters::Emitters;
+use crate::misc::alpha_iors::AlphaIors;
+use crate::occupancy::Occupancy;
+use crate::octree::Octree;
+use crate::volume::Volume;
+use crate::vulkan::Vulkan;
+
+// Uploads the parts of the volume that were edited by the systems before it, so every system can
+// simply write to the volume.
+pub struct UploadSystem;
+
+impl<'a> System<'a> for UploadSystem {
+    type SystemData = (
+        WriteExpect<'a, Vulkan>,
+        WriteExpect<'a, Volume>,
+        WriteExpect<'a, Atlas>,
+        WriteExpect<'a, Octree>,
+        WriteExpect<'a, Occupancy>,
+        WriteExpect<'a, Emitters>,
+        WriteExpect<'a, AlphaIors>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut vulkan,
+            mut texture,
+            mut atlas,
+            mut octree,
+            mut occupancy,
+            mut emitters,
+            mut alpha_iors,
+        ): Self::SystemData,
+    ) {
+        if alpha_iors.take_changed() {
+            vulkan.update_storage(9, &alpha_iors.iors);
+        }
+
+        let dirty = texture.take_dirty();
+        if dirty.is_empty() {
+            return;
+        }
+
+        let size = atlas.size();
+        let regions = atlas.update(&texture, &dirty);
+        if atlas.size() != size {
+            let size = atlas.size();
+            vulkan.resize_texture(1, size.x as u32, size.y as u32, size.z as u32);
+        }
+        vulkan.update_texture_regions(1, &regions);
+        vulkan.update_storage(5, &atlas.pages);
+
+        octree.update(&texture);
+        vulkan.update_storage(4, &octree.nodes);
+
+        occupancy.update(&texture, &dirty);
+        vulkan.update_storage(6, &occupancy.bits);
+
+        if emitters.update(&texture, &dirty) {
+            vulkan.update_storage(8, &emitters.grid);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use specs::{System, WriteExpect};
+
+use crate::volume::Volume;
+use crate::water::Water;
+
+pub struct WaterSystem;
+
+impl<'a> System<'a> for WaterSystem {
+    type SystemData = (WriteExpect<'a, Volume>, WriteExpect<'a, Water>);
+
+    fn run(&mut self, (mut texture, mut water): Self::SystemData) {
+        if !water.is_settled() {
+            water.step(&mut texture);
+        }
+    }
+}
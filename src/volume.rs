@@ -1,106 +1,250 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
+use std::io::{self, BufReader, BufWriter};
 use std::path::Path;
 
-use noise::{Fbm, NoiseFn};
+use crate::format::vox::{self, VoxError};
+use crate::format::world::{self, Compression, Header, WorldError};
+use crate::math::{self, IVec3};
+use crate::voxel::Voxel;
+
+pub const CHUNK_SIZE: usize = 32;
+
+pub struct Chunk {
+    pub data: Vec<Voxel>,
+}
+
+impl Chunk {
+    fn new() -> Self {
+        Self {
+            data: vec![Voxel::EMPTY; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE],
+        }
+    }
+
+    #[inline]
+    pub fn index(pos: IVec3) -> usize {
+        let pos = pos.map(|value| value.rem_euclid(CHUNK_SIZE as i32) as usize);
+        pos.z * CHUNK_SIZE * CHUNK_SIZE + pos.y * CHUNK_SIZE + pos.x
+    }
+}
+
+// An inclusive box of voxels in world coordinates that changed since the GPU copy was last updated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyBox {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfBounds(pub IVec3);
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Voxel position ({}, {}, {}) is outside of the world",
+            self.0.x, self.0.y, self.0.z
+        )
+    }
+}
+
+impl Error for OutOfBounds {}
 
 pub struct Volume {
-    size: usize,
-    pub data: Vec<u32>,
+    size: IVec3,
+    origin: IVec3,
+    chunks: HashMap<IVec3, Chunk>,
+    dirty: HashMap<IVec3, DirtyBox>,
 }
 
 impl Volume {
-    pub fn from_file<P>(path: P, size: usize) -> Self
+    pub fn from_file<P>(path: P) -> Result<Self, WorldError>
     where
         P: AsRef<Path>,
     {
-        let mut data = Vec::with_capacity(size * size * size);
-        let mut file = BufReader::new(File::open(path).unwrap());
-
-        while data.len() < size * size * size {
-            let mut bytes = [0; 4];
-            let mut total = 0;
-            file.read_exact(&mut bytes).unwrap();
-            for i in 0..4 {
-                total += (bytes[i] as u32) << i * 8;
-            }
-            data.push(total);
+        let (header, data) = world::read(BufReader::new(File::open(path)?))?;
+        let size = IVec3::new(header.width as i32, header.height as i32, header.depth as i32);
+
+        let mut volume = Self::from_data(size, data);
+        volume.origin = IVec3::from(header.origin);
+        Ok(volume)
+    }
+
+    pub fn save<P>(&self, path: P, compression: Compression) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let size = self.size;
+        let header = Header {
+            width: size.x as u32,
+            height: size.y as u32,
+            depth: size.z as u32,
+            origin: self.origin.into(),
+            compression,
+        };
+
+        let voxels = self.iter_box(IVec3::zeros(), size.add_scalar(-1)).map(|(_, voxel)| voxel);
+
+        world::write(BufWriter::new(File::create(path)?), &header, voxels)
+    }
+
+    pub fn from_vox_file<P>(path: P) -> Result<Self, VoxError>
+    where
+        P: AsRef<Path>,
+    {
+        let voxels = vox::read(BufReader::new(File::open(path)?))?;
+
+        let mut min = IVec3::repeat(i32::MAX);
+        let mut max = IVec3::repeat(i32::MIN);
+        for (pos, _) in &voxels {
+            min = min.inf(pos);
+            max = max.sup(pos);
         }
 
-        Self::create_volume(size, data)
+        let size = if voxels.is_empty() {
+            IVec3::repeat(1)
+        } else {
+            (max - min).add_scalar(1)
+        };
+
+        let mut volume = Self::new(size);
+        for (pos, voxel) in voxels {
+            volume.set(pos - min, voxel);
+        }
+
+        Ok(volume)
     }
 
-    pub fn new(size: usize) -> Self {
-        let data = Self::generate_data(size);
-        Self::create_volume(size, data)
+    pub fn save_vox<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        vox::write(BufWriter::new(File::create(path)?), self)
     }
 
-    fn create_volume(size: usize, data: Vec<u32>) -> Self {
-        Self {
+    // The origin is the world position of the first voxel and centres the volume around 0 by default.
+    pub fn new(size: IVec3) -> Self {
+        Self::from_data(size, Vec::new())
+    }
+
+    fn from_data(size: IVec3, data: Vec<Voxel>) -> Self {
+        let mut volume = Self {
             size,
-            data,
+            origin: -size / 2,
+            chunks: HashMap::new(),
+            dirty: HashMap::new(),
+        };
+
+        for (i, value) in data.into_iter().enumerate() {
+            volume.set(Self::to_pos(i, size), value);
         }
+
+        volume
     }
 
     #[inline]
-    fn to_color(r: u8, g: u8, b: u8, factor: f64) -> u32 {
-        (((b as f64 * factor) as u32) << 16)
-            + (((g as f64 * factor) as u32) << 8)
-            + (r as f64 * factor) as u32
-    }
-
-    fn generate_data(size: usize) -> Vec<u32> {
-        let mut data = Vec::with_capacity(size * size * size);
-        let fbm = Fbm::new();
-
-        for z in 0..size {
-            for y in 0..size {
-                for x in 0..size {
-                    let noise = fbm.get([x as f64 * 0.002, y as f64 * 0.002, z as f64 * 0.002]);
-                    data.push(
-                        (noise - (y as f64 - size as f64 / 4.0) * 0.01 > 0.0) as u32,
-                    );
-                }
-            }
+    fn to_pos(index: usize, size: IVec3) -> IVec3 {
+        let (width, height) = (size.x as usize, size.y as usize);
+        IVec3::new(
+            (index % width) as i32,
+            (index / width % height) as i32,
+            (index / (width * height)) as i32,
+        )
+    }
+
+    #[inline]
+    fn chunk_key(pos: IVec3) -> IVec3 {
+        pos.map(|value| value.div_euclid(CHUNK_SIZE as i32))
+    }
+
+    pub fn contains(&self, pos: IVec3) -> bool {
+        pos.iter().zip(self.size.iter()).all(|(&value, &size)| value >= 0 && value < size)
+    }
+
+    // Positions outside of the world are empty.
+    pub fn get(&self, pos: IVec3) -> Voxel {
+        if !self.contains(pos) {
+            return Voxel::EMPTY;
         }
 
-        for z in 0..size {
-            for x in 0..size {
-                let mut depth = 0;
-                for y in 0..size {
-                    let index = size * size * z + size * (size - y - 1) + x;
-                    if data[index] > 0 {
-                        let factor = fbm.get([x as f64, y as f64, z as f64]);
-                        if depth == 0 {
-                            data[index] =
-                                (15 << 28) + Self::to_color(0b01000010, 0b01111100, 0b00111010, 1.0 - factor)
-                        } else if depth < 5 {
-                            data[index] =
-                                (15 << 28) + Self::to_color(0b01001010, 0b01000010, 0b00110010, 1.0 - factor * 0.5)
-                        } else {
-                            data[index] =
-                                (15 << 28) + Self::to_color(0b01000010, 0b00111100, 0b00110010, 1.0 - factor * 0.1)
-                        }
-
-                        depth += 1;
-                    } else {
-                        depth = 0;
-                    }
-                }
-            }
+        match self.chunks.get(&Self::chunk_key(pos)) {
+            Some(chunk) => chunk.data[Chunk::index(pos)],
+            None => Voxel::EMPTY,
         }
+    }
 
-        // let mut file = std::fs::File::create("assets/world").unwrap();
-        // let slice =
-        //     unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 4) };
-        // file.write_all(slice).unwrap();
+    // Writes outside of the world are ignored, use try_set to find out about them.
+    pub fn set(&mut self, pos: IVec3, value: Voxel) {
+        self.try_set(pos, value).ok();
+    }
+
+    pub fn try_set(&mut self, pos: IVec3, value: Voxel) -> Result<(), OutOfBounds> {
+        if !self.contains(pos) {
+            return Err(OutOfBounds(pos));
+        }
+
+        let key = Self::chunk_key(pos);
+        if value.is_empty() && !self.chunks.contains_key(&key) {
+            return Ok(());
+        }
 
-        data
+        let chunk = self.chunks.entry(key).or_insert_with(Chunk::new);
+        let index = Chunk::index(pos);
+        if chunk.data[index] == value {
+            return Ok(());
+        }
+        chunk.data[index] = value;
+
+        self.dirty
+            .entry(key)
+            .and_modify(|dirty| {
+                dirty.min = dirty.min.inf(&pos);
+                dirty.max = dirty.max.sup(&pos);
+            })
+            .or_insert(DirtyBox { min: pos, max: pos });
+
+        Ok(())
+    }
+
+    // Every voxel in the inclusive box from min to max, clipped to the world.
+    pub fn iter_box(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = (IVec3, Voxel)> + '_ {
+        let min = min.sup(&IVec3::zeros());
+        let max = max.inf(&self.size.add_scalar(-1));
+        math::box_positions(min, max).map(move |pos| (pos, self.get(pos)))
+    }
+
+    // The up to 26 voxels surrounding pos that are inside of the world.
+    pub fn neighbours(&self, pos: IVec3) -> impl Iterator<Item = (IVec3, Voxel)> + '_ {
+        self.iter_box(pos.add_scalar(-1), pos.add_scalar(1))
+            .filter(move |(neighbour, _)| *neighbour != pos)
+    }
+
+    // Returns the changed box of every chunk that was written since the last call, per chunk key.
+    pub fn take_dirty(&mut self) -> HashMap<IVec3, DirtyBox> {
+        std::mem::take(&mut self.dirty)
     }
 
-    pub fn size(&self) -> usize {
+    pub fn chunk(&self, key: &IVec3) -> Option<&Chunk> {
+        self.chunks.get(key)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&IVec3, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn size(&self) -> IVec3 {
         self.size
     }
+
+    pub fn origin(&self) -> IVec3 {
+        self.origin
+    }
+
+    pub fn chunks_per_axis(&self) -> IVec3 {
+        self.size.map(|value| (value + CHUNK_SIZE as i32 - 1) / CHUNK_SIZE as i32)
+    }
 }
 
 impl Drop for Volume {
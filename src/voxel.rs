@@ -0,0 +1,134 @@
+// A voxel is packed into 32 bits, which the shader reads as RGBA bytes. The 24 least significant
+// bits hold the colour, followed by 4 bits of reflectivity and 4 bits of transparency. A voxel
+// with a transparency of 0 is empty.
+//
+// A fully reflective voxel would never show its colour, so a reflectivity of 15 marks an opaque
+// emissive voxel instead, which glows in its colour with the transparency bits as its intensity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct Voxel(u32);
+
+const _: () = assert!(Voxel::new(0x12, 0x34, 0x56, 0x7, 0x8).to_bits() == 0x7856_3412);
+const _: () = assert!(Voxel::new(0xff, 0xff, 0xff, 0x0, 0xf).is_empty());
+const _: () = assert!(!Voxel::new(0x00, 0x00, 0x00, 0x1, 0x0).is_empty());
+const _: () = assert!(Voxel::emissive(0xff, 0x80, 0x00, 0x9).emission() == 0x9);
+const _: () = assert!(Voxel::emissive(0xff, 0x80, 0x00, 0x9).transparency() == 15);
+const _: () = assert!(Voxel::emissive(0xff, 0x80, 0x00, 0x0).is_emissive());
+const _: () = assert!(!Voxel::new(0xff, 0x80, 0x00, 0xf, 0xe).is_emissive());
+
+impl Voxel {
+    pub const EMPTY: Self = Self(0);
+
+    pub const fn new(r: u8, g: u8, b: u8, transparency: u8, reflectivity: u8) -> Self {
+        Self(
+            ((transparency as u32 & 15) << 28)
+                | ((reflectivity as u32 & 15) << 24)
+                | ((b as u32) << 16)
+                | ((g as u32) << 8)
+                | r as u32,
+        )
+    }
+
+    pub const fn solid(r: u8, g: u8, b: u8) -> Self {
+        Self::new(r, g, b, 15, 0)
+    }
+
+    pub const fn emissive(r: u8, g: u8, b: u8, intensity: u8) -> Self {
+        let intensity = if intensity & 15 == 0 { 1 } else { intensity };
+        Self::new(r, g, b, intensity, 15)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn r(self) -> u8 {
+        self.0 as u8
+    }
+
+    pub const fn g(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub const fn b(self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    pub const fn rgb(self) -> (u8, u8, u8) {
+        (self.r(), self.g(), self.b())
+    }
+
+    // Keeps the material of the voxel, including its emission
+    pub const fn with_rgb(self, r: u8, g: u8, b: u8) -> Self {
+        Self((self.0 & 0xff00_0000) | ((b as u32) << 16) | ((g as u32) << 8) | r as u32)
+    }
+
+    pub const fn reflectivity(self) -> u8 {
+        if self.is_emissive() {
+            0
+        } else {
+            (self.0 >> 24) as u8 & 15
+        }
+    }
+
+    pub const fn transparency(self) -> u8 {
+        if self.is_emissive() {
+            15
+        } else {
+            (self.0 >> 28) as u8
+        }
+    }
+
+    pub const fn emission(self) -> u8 {
+        if self.is_emissive() {
+            (self.0 >> 28) as u8
+        } else {
+            0
+        }
+    }
+
+    pub const fn is_emissive(self) -> bool {
+        (self.0 >> 24) as u8 & 15 == 15 && !self.is_empty()
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 >> 28 == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The shader reads a voxel as the RGBA bytes of a little endian u32
+    fn bytes(voxel: Voxel) -> [u8; 4] {
+        voxel.to_bits().to_le_bytes()
+    }
+
+    #[test]
+    fn colour_bytes() {
+        let [r, g, b, _] = bytes(Voxel::new(0x12, 0x34, 0x56, 0x7, 0x8));
+        assert_eq!((r, g, b), (0x12, 0x34, 0x56));
+    }
+
+    #[test]
+    fn alpha_byte() {
+        for transparency in 0..16 {
+            for reflectivity in 0..16 {
+                let [.., a] = bytes(Voxel::new(0xff, 0xff, 0xff, transparency, reflectivity));
+                assert_eq!(a >> 4, transparency);
+                assert_eq!(a & 15, reflectivity);
+            }
+        }
+    }
+
+    #[test]
+    fn masks_out_of_range_nibbles() {
+        let [r, g, b, a] = bytes(Voxel::new(0x01, 0x02, 0x03, 0x1f, 0x2e));
+        assert_eq!((r, g, b, a), (0x01, 0x02, 0x03, 0xfe));
+    }
+}
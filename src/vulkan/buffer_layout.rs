@@ -2,9 +2,18 @@ use std::collections::HashMap;
 
 use ash::vk;
 
+use super::{Buffer, DynamicBuffer, DynamicTexture, StaticTexture};
+
 pub struct BufferLayout<T> {
     pub stage_flags: vk::ShaderStageFlags,
     pub buffer: T,
 }
 
-pub type BufferLayouts<T> = HashMap<u32, BufferLayout<T>>;
\ No newline at end of file
+pub type BufferLayouts<T> = HashMap<u32, BufferLayout<T>>;
+
+pub struct Bindings<'a> {
+    pub uniforms: &'a BufferLayouts<Buffer>,
+    pub textures: &'a BufferLayouts<StaticTexture>,
+    pub dynamic_textures: &'a BufferLayouts<DynamicTexture>,
+    pub dynamic_buffers: &'a BufferLayouts<DynamicBuffer>,
+}
\ No newline at end of file
@@ -4,8 +4,7 @@ use ash::version::DeviceV1_0;
 use ash::vk;
 
 use super::{
-    Buffer, DescriptorSetLayout, DynamicTexture, LogicalDevice, Sampler, StaticTexture,
-    BufferLayout, BufferLayouts,
+    Bindings, BufferLayout, DescriptorSetLayout, LogicalDevice, Sampler,
 };
 
 pub struct DescriptorPool {
@@ -18,13 +17,18 @@ impl DescriptorPool {
         logical_device: &LogicalDevice,
         swap_chain_images_len: usize,
         descriptor_set_layout: &DescriptorSetLayout,
-        buffers: &BufferLayouts<Buffer>,
-        textures: &BufferLayouts<StaticTexture>,
-        dynamic_textures: &BufferLayouts<DynamicTexture>,
+        bindings: &Bindings,
         sampler: &Sampler,
     ) -> Self {
+        let Bindings {
+            uniforms: buffers,
+            textures,
+            dynamic_textures,
+            dynamic_buffers,
+        } = *bindings;
+
         let mut sizes = Vec::new();
-        for (_, BufferLayout { buffer, .. }) in buffers {
+        for BufferLayout { buffer, .. } in buffers.values() {
             sizes.push(vk::DescriptorPoolSize {
                 ty: buffer.descriptor_type,
                 descriptor_count: swap_chain_images_len as u32,
@@ -45,6 +49,13 @@ impl DescriptorPool {
             });
         }
 
+        for BufferLayout { buffer, .. } in dynamic_buffers.values() {
+            sizes.push(vk::DescriptorPoolSize {
+                ty: buffer.descriptor_type,
+                descriptor_count: swap_chain_images_len as u32,
+            });
+        }
+
         let create_info = vk::DescriptorPoolCreateInfo {
             s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
             p_next: ptr::null(),
@@ -80,7 +91,7 @@ impl DescriptorPool {
 
         for i in 0..swap_chain_images_len {
             let mut descriptor_sets = Vec::new();
-            let mut buffer_infos = Vec::new();
+            let mut buffer_infos = Vec::with_capacity(buffers.len());
             for (binding, BufferLayout { buffer, .. }) in buffers {
                 buffer_infos.push(vk::DescriptorBufferInfo {
                     buffer: buffer.buffer(i),
@@ -103,7 +114,7 @@ impl DescriptorPool {
                 descriptor_sets.push(buffer_set_write);
             }
 
-            let mut texture_infos = Vec::new();
+            let mut texture_infos = Vec::with_capacity(textures.len());
             for (binding, BufferLayout { buffer, .. }) in textures {
                 texture_infos.push(vk::DescriptorImageInfo {
                     image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
@@ -126,7 +137,7 @@ impl DescriptorPool {
                 descriptor_sets.push(sampler_set_write);
             }
 
-            let mut dynamic_texture_infos = Vec::new();
+            let mut dynamic_texture_infos = Vec::with_capacity(dynamic_textures.len());
             for (binding, BufferLayout { buffer, .. }) in dynamic_textures {
                 dynamic_texture_infos.push(vk::DescriptorImageInfo {
                     image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
@@ -149,6 +160,29 @@ impl DescriptorPool {
                 descriptor_sets.push(sampler_set_write);
             }
 
+            let mut dynamic_buffer_infos = Vec::with_capacity(dynamic_buffers.len());
+            for (binding, BufferLayout { buffer, .. }) in dynamic_buffers {
+                dynamic_buffer_infos.push(vk::DescriptorBufferInfo {
+                    buffer: buffer.value,
+                    offset: 0,
+                    range: buffer.size,
+                });
+
+                let buffer_set_write = vk::WriteDescriptorSet {
+                    s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                    p_next: ptr::null(),
+                    dst_set: sets[i],
+                    dst_binding: *binding,
+                    dst_array_element: 0,
+                    descriptor_count: 1,
+                    descriptor_type: buffer.descriptor_type,
+                    p_image_info: ptr::null(),
+                    p_buffer_info: dynamic_buffer_infos.last().unwrap(),
+                    p_texel_buffer_view: ptr::null(),
+                };
+                descriptor_sets.push(buffer_set_write);
+            }
+
             unsafe {
                 logical_device
                     .value
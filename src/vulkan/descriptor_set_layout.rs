@@ -3,9 +3,7 @@ use std::ptr;
 use ash::version::DeviceV1_0;
 use ash::vk;
 
-use super::{
-    Buffer, DynamicTexture, LogicalDevice, StaticTexture, BufferLayout, BufferLayouts,
-};
+use super::{Bindings, BufferLayout, LogicalDevice};
 
 pub struct DescriptorSetLayout {
     pub value: vk::DescriptorSetLayout,
@@ -14,12 +12,10 @@ pub struct DescriptorSetLayout {
 impl DescriptorSetLayout {
     pub fn new(
         logical_device: &LogicalDevice,
-        buffers: &BufferLayouts<Buffer>,
-        textures: &BufferLayouts<StaticTexture>,
-        dynamic_textures: &BufferLayouts<DynamicTexture>,
+        layouts: &Bindings,
     ) -> Self {
         let mut bindings = Vec::new();
-        for (binding, BufferLayout { stage_flags, buffer }) in buffers {
+        for (binding, BufferLayout { stage_flags, buffer }) in layouts.uniforms {
             bindings.push(vk::DescriptorSetLayoutBinding {
                 binding: *binding,
                 descriptor_type: buffer.descriptor_type,
@@ -29,7 +25,7 @@ impl DescriptorSetLayout {
             });
         }
 
-        for (binding, BufferLayout { stage_flags, .. }) in textures {
+        for (binding, BufferLayout { stage_flags, .. }) in layouts.textures {
             bindings.push(vk::DescriptorSetLayoutBinding {
                 binding: *binding,
                 descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
@@ -39,7 +35,7 @@ impl DescriptorSetLayout {
             });
         }
 
-        for (binding, BufferLayout { stage_flags, .. }) in dynamic_textures {
+        for (binding, BufferLayout { stage_flags, .. }) in layouts.dynamic_textures {
             bindings.push(vk::DescriptorSetLayoutBinding {
                 binding: *binding,
                 descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
@@ -49,6 +45,16 @@ impl DescriptorSetLayout {
             });
         }
 
+        for (binding, BufferLayout { stage_flags, buffer }) in layouts.dynamic_buffers {
+            bindings.push(vk::DescriptorSetLayoutBinding {
+                binding: *binding,
+                descriptor_type: buffer.descriptor_type,
+                descriptor_count: 1,
+                stage_flags: *stage_flags,
+                p_immutable_samplers: ptr::null(),
+            });
+        }
+
         let layout_info = vk::DescriptorSetLayoutCreateInfo {
             s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
             p_next: ptr::null(),
@@ -0,0 +1,150 @@
+use std::ptr;
+
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+use super::util::create_buffer;
+use super::{CommandPool, LogicalDevice, Queues};
+
+fn buffer_barrier(
+    logical_device: &LogicalDevice,
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    (src_stages, src_access_mask): (vk::PipelineStageFlags, vk::AccessFlags),
+    (dst_stages, dst_access_mask): (vk::PipelineStageFlags, vk::AccessFlags),
+) {
+    let barrier = vk::BufferMemoryBarrier {
+        s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+        p_next: ptr::null(),
+        src_access_mask,
+        dst_access_mask,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        buffer,
+        offset: 0,
+        size: vk::WHOLE_SIZE,
+    };
+
+    unsafe {
+        logical_device.value.cmd_pipeline_barrier(
+            command_buffer,
+            src_stages,
+            dst_stages,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[barrier],
+            &[],
+        );
+    }
+}
+
+pub struct DynamicBuffer {
+    pub staging: vk::Buffer,
+    pub staging_memory: vk::DeviceMemory,
+    pub value: vk::Buffer,
+    pub memory: vk::DeviceMemory,
+    pub descriptor_type: vk::DescriptorType,
+    pub usage: vk::BufferUsageFlags,
+    pub size: vk::DeviceSize,
+}
+
+impl DynamicBuffer {
+    pub fn new(
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        logical_device: &LogicalDevice,
+        usage: vk::BufferUsageFlags,
+        descriptor_type: vk::DescriptorType,
+        size: vk::DeviceSize,
+    ) -> Self {
+        let (staging, staging_memory) = create_buffer(
+            memory_properties,
+            logical_device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
+        let (value, memory) = create_buffer(
+            memory_properties,
+            logical_device,
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+
+        Self {
+            staging,
+            staging_memory,
+            value,
+            memory,
+            descriptor_type,
+            usage,
+            size,
+        }
+    }
+
+    pub fn update<T>(
+        &self,
+        logical_device: &LogicalDevice,
+        command_pool: &CommandPool,
+        queues: &Queues,
+        data: &[T],
+    ) {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        if size == 0 {
+            return;
+        }
+
+        let raw_data = unsafe {
+            logical_device
+                .value
+                .map_memory(self.staging_memory, 0, size, vk::MemoryMapFlags::empty())
+        }
+        .expect("Failed to map memory") as *mut T;
+
+        unsafe {
+            raw_data.copy_from_nonoverlapping(data.as_ptr(), data.len());
+            logical_device.value.unmap_memory(self.staging_memory);
+        }
+
+        let region = vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size,
+        };
+
+        let command_buffer = command_pool.begin_single_time_commands(logical_device);
+        buffer_barrier(
+            logical_device,
+            command_buffer,
+            self.value,
+            (vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ),
+            (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE),
+        );
+        unsafe {
+            logical_device.value.cmd_copy_buffer(
+                command_buffer,
+                self.staging,
+                self.value,
+                &[region],
+            );
+        }
+        buffer_barrier(
+            logical_device,
+            command_buffer,
+            self.value,
+            (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE),
+            (vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ),
+        );
+        command_pool.end_single_time_commands(logical_device, command_buffer, queues.graphics);
+    }
+
+    pub fn destroy(&self, logical_device: &LogicalDevice) {
+        unsafe {
+            logical_device.value.destroy_buffer(self.staging, None);
+            logical_device.value.free_memory(self.staging_memory, None);
+            logical_device.value.destroy_buffer(self.value, None);
+            logical_device.value.free_memory(self.memory, None);
+        }
+    }
+}
@@ -11,6 +11,7 @@ mod command_pool;
 pub mod constants;
 mod descriptor_pool;
 mod descriptor_set_layout;
+mod dynamic_buffer;
 mod framebuffers;
 mod image_views;
 mod instance;
@@ -34,6 +35,7 @@ use buffer::Buffer;
 use command_pool::CommandPool;
 use descriptor_pool::DescriptorPool;
 use descriptor_set_layout::DescriptorSetLayout;
+use dynamic_buffer::DynamicBuffer;
 use framebuffers::Framebuffers;
 use image_views::ImageViews;
 use instance::Instance;
@@ -46,10 +48,8 @@ use sampler::Sampler;
 use surface::Surface;
 use swap_chain::SwapChain;
 use sync_objects::SyncObjects;
-use texture::{DynamicTexture, StaticTexture};
-use buffer_layout::{BufferLayout, BufferLayouts};
-
-use crate::volume::Volume;
+use texture::{DynamicTexture, Region, StaticTexture};
+use buffer_layout::{Bindings, BufferLayout, BufferLayouts};
 
 pub struct VulkanBuilder {
     _entry: ash::Entry,
@@ -68,6 +68,7 @@ pub struct VulkanBuilder {
     uniforms: BufferLayouts<Buffer>,
     textures: BufferLayouts<StaticTexture>,
     dynamic_textures: BufferLayouts<DynamicTexture>,
+    dynamic_buffers: BufferLayouts<DynamicBuffer>,
 }
 
 impl VulkanBuilder {
@@ -119,6 +120,7 @@ impl VulkanBuilder {
             uniforms: HashMap::new(),
             textures: HashMap::new(),
             dynamic_textures: HashMap::new(),
+            dynamic_buffers: HashMap::new(),
         }
     }
 
@@ -159,33 +161,50 @@ impl VulkanBuilder {
         )
     }
 
-    pub fn with_storage<T>(self, binding: u32, stage_flags: vk::ShaderStageFlags, multiplier: vk::DeviceSize) -> Self {
-        self.with_buffer::<T>(
-            binding,
-            stage_flags,
+    pub fn with_dynamic_storage<T>(
+        mut self,
+        binding: u32,
+        stage_flags: vk::ShaderStageFlags,
+        len: usize,
+    ) -> Self {
+        let buffer = DynamicBuffer::new(
+            self.memory_properties,
+            &self.logical_device,
             vk::BufferUsageFlags::STORAGE_BUFFER,
             vk::DescriptorType::STORAGE_BUFFER,
-            multiplier,
-        )
+            (std::mem::size_of::<T>() * len.max(1)) as vk::DeviceSize,
+        );
+
+        self.dynamic_buffers.insert(
+            binding,
+            BufferLayout {
+                stage_flags,
+                buffer,
+            },
+        );
+        self
     }
 
-    pub fn with_texture(
+    pub fn with_texture<T>(
         mut self,
         binding: u32,
         stage_flags: vk::ShaderStageFlags,
-        volume: &Volume,
+        width: u32,
+        height: u32,
+        depth: u32,
+        data: &[T],
     ) -> Self {
         let texture = StaticTexture::new_3d(
             self.memory_properties,
             &self.logical_device,
             &self.command_pool,
             &self.queues,
-            volume.size() as u32,
-            volume.size() as u32,
-            volume.size() as u32,
+            width,
+            height,
+            depth,
             4,
             vk::Format::R8G8B8A8_UINT,
-            &volume.data,
+            data,
         );
 
         self.textures.insert(
@@ -202,16 +221,18 @@ impl VulkanBuilder {
         mut self,
         binding: u32,
         stage_flags: vk::ShaderStageFlags,
-        volume: &Volume,
+        width: u32,
+        height: u32,
+        depth: u32,
     ) -> Self {
         let texture = DynamicTexture::new_3d(
             self.memory_properties,
             &self.logical_device,
             &self.command_pool,
             &self.queues,
-            volume.size() as u32,
-            volume.size() as u32,
-            volume.size() as u32,
+            width,
+            height,
+            depth,
             4,
             vk::Format::R8G8B8A8_UINT,
         );
@@ -230,9 +251,12 @@ impl VulkanBuilder {
         let sampler = Sampler::new(&self.logical_device);
         let descriptor_set_layout = DescriptorSetLayout::new(
             &self.logical_device,
-            &self.uniforms,
-            &self.textures,
-            &self.dynamic_textures,
+            &Bindings {
+                uniforms: &self.uniforms,
+                textures: &self.textures,
+                dynamic_textures: &self.dynamic_textures,
+                dynamic_buffers: &self.dynamic_buffers,
+            },
         );
         let pipeline = Pipeline::new(
             &self.logical_device,
@@ -244,9 +268,12 @@ impl VulkanBuilder {
             &self.logical_device,
             self.swap_chain.images.len(),
             &descriptor_set_layout,
-            &self.uniforms,
-            &self.textures,
-            &self.dynamic_textures,
+            &Bindings {
+                uniforms: &self.uniforms,
+                textures: &self.textures,
+                dynamic_textures: &self.dynamic_textures,
+                dynamic_buffers: &self.dynamic_buffers,
+            },
             &sampler,
         );
         self.command_pool.create_buffers(
@@ -283,6 +310,7 @@ impl VulkanBuilder {
             uniforms: self.uniforms,
             textures: self.textures,
             dynamic_textures: self.dynamic_textures,
+            dynamic_buffers: self.dynamic_buffers,
             sampler,
             image_index: 0,
         }
@@ -340,6 +368,7 @@ pub struct Vulkan {
     uniforms: BufferLayouts<Buffer>,
     textures: BufferLayouts<StaticTexture>,
     dynamic_textures: BufferLayouts<DynamicTexture>,
+    dynamic_buffers: BufferLayouts<DynamicBuffer>,
     sampler: Sampler,
     image_index: usize,
 }
@@ -482,8 +511,22 @@ impl Vulkan {
         }
     }
 
-    pub fn update_texture<T>(&self, binding: u32, data: &Vec<T>) {
-        self.dynamic_textures[&binding].buffer.update(
+    pub fn update_texture_regions<T>(&self, binding: u32, regions: &[Region<T>]) {
+        self.dynamic_textures[&binding].buffer.update_regions(
+            &self.logical_device,
+            &self.command_pool,
+            &self.queues,
+            regions,
+        );
+    }
+
+    pub fn update_storage<T>(&mut self, binding: u32, data: &[T]) {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        if size > self.dynamic_buffers[&binding].buffer.size {
+            self.resize_storage(binding, size * 2);
+        }
+
+        self.dynamic_buffers[&binding].buffer.update(
             &self.logical_device,
             &self.command_pool,
             &self.queues,
@@ -491,6 +534,70 @@ impl Vulkan {
         );
     }
 
+    fn resize_storage(&mut self, binding: u32, size: vk::DeviceSize) {
+        unsafe { self.logical_device.value.device_wait_idle() }
+            .expect("Failed to wait for device idle");
+
+        let buffer = &mut self.dynamic_buffers.get_mut(&binding).unwrap().buffer;
+        buffer.destroy(&self.logical_device);
+        *buffer = DynamicBuffer::new(
+            self.memory_properties,
+            &self.logical_device,
+            buffer.usage,
+            buffer.descriptor_type,
+            size,
+        );
+
+        self.recreate_descriptor_sets();
+    }
+
+    // Creates the texture again at the new size, which leaves it empty
+    pub fn resize_texture(&mut self, binding: u32, width: u32, height: u32, depth: u32) {
+        unsafe { self.logical_device.value.device_wait_idle() }
+            .expect("Failed to wait for device idle");
+
+        let texture = &mut self.dynamic_textures.get_mut(&binding).unwrap().buffer;
+        texture.destroy(&self.logical_device);
+        *texture = DynamicTexture::new_3d(
+            self.memory_properties,
+            &self.logical_device,
+            &self.command_pool,
+            &self.queues,
+            width,
+            height,
+            depth,
+            texture.dimensions,
+            texture.format,
+        );
+
+        self.recreate_descriptor_sets();
+    }
+
+    fn recreate_descriptor_sets(&mut self) {
+        self.command_pool.free_buffers(&self.logical_device);
+        self.descriptor_pool.destroy(&self.logical_device);
+        self.descriptor_pool = DescriptorPool::new(
+            &self.logical_device,
+            self.swap_chain.images.len(),
+            &self.descriptor_set_layout,
+            &Bindings {
+                uniforms: &self.uniforms,
+                textures: &self.textures,
+                dynamic_textures: &self.dynamic_textures,
+                dynamic_buffers: &self.dynamic_buffers,
+            },
+            &self.sampler,
+        );
+        self.command_pool.create_buffers(
+            &self.logical_device,
+            &self.swap_chain,
+            &self.render_pass,
+            &self.framebuffers,
+            &self.pipeline,
+            &self.descriptor_pool,
+        );
+    }
+
     pub fn framebuffer_resized(&mut self) {
         self.framebuffer_resized = true;
     }
@@ -549,9 +656,12 @@ impl Vulkan {
             &self.logical_device,
             self.swap_chain.images.len(),
             &self.descriptor_set_layout,
-            &self.uniforms,
-            &self.textures,
-            &self.dynamic_textures,
+            &Bindings {
+                uniforms: &self.uniforms,
+                textures: &self.textures,
+                dynamic_textures: &self.dynamic_textures,
+                dynamic_buffers: &self.dynamic_buffers,
+            },
             &self.sampler,
         );
         self.command_pool.create_buffers(
@@ -578,6 +688,9 @@ impl Drop for Vulkan {
         for texture in self.dynamic_textures.values_mut() {
             texture.buffer.destroy(&self.logical_device);
         }
+        for buffer in self.dynamic_buffers.values_mut() {
+            buffer.buffer.destroy(&self.logical_device);
+        }
         self.command_pool.destroy(&self.logical_device);
         self.descriptor_set_layout.destroy(&self.logical_device);
         self.logical_device.destroy();
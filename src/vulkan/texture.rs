@@ -14,6 +14,23 @@ type TextureComponents = (
     vk::DeviceMemory,
 );
 
+// A box of texels to upload. The data starts at the first texel of the box and is laid out in rows
+// of row_length texels and images of image_height rows, so a box can be read straight out of a
+// larger array. Only the texels inside the box are staged.
+pub struct Region<'a, T> {
+    pub offset: (u32, u32, u32),
+    pub extent: (u32, u32, u32),
+    pub row_length: u32,
+    pub image_height: u32,
+    pub data: &'a [T],
+}
+
+impl<'a, T> Region<'a, T> {
+    pub fn texels(&self) -> usize {
+        self.extent.0 as usize * self.extent.1 as usize * self.extent.2 as usize
+    }
+}
+
 pub struct StaticTexture {
     pub value: vk::Image,
     pub memory: vk::DeviceMemory,
@@ -30,6 +47,7 @@ pub struct DynamicTexture {
     pub height: u32,
     pub depth: u32,
     pub dimensions: u32,
+    pub format: vk::Format,
 }
 
 fn new_1d(
@@ -176,44 +194,6 @@ fn new(
     (value, memory, image_view, buffer, buffer_memory)
 }
 
-fn copy_buffer_to_image(
-    logical_device: &LogicalDevice,
-    command_buffer: vk::CommandBuffer,
-    buffer: vk::Buffer,
-    image: vk::Image,
-    width: u32,
-    height: u32,
-    depth: u32,
-) {
-    let region = vk::BufferImageCopy {
-        buffer_offset: 0,
-        buffer_row_length: 0,
-        buffer_image_height: 0,
-        image_subresource: vk::ImageSubresourceLayers {
-            aspect_mask: vk::ImageAspectFlags::COLOR,
-            mip_level: 0,
-            base_array_layer: 0,
-            layer_count: 1,
-        },
-        image_offset: Default::default(),
-        image_extent: vk::Extent3D {
-            width,
-            height,
-            depth,
-        },
-    };
-
-    unsafe {
-        logical_device.value.cmd_copy_buffer_to_image(
-            command_buffer,
-            buffer,
-            image,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            &[region],
-        );
-    }
-}
-
 fn transition_image_layout(
     logical_device: &LogicalDevice,
     command_buffer: vk::CommandBuffer,
@@ -276,16 +256,20 @@ fn transition_image_layout(
     }
 }
 
-fn load_data<T>(
+// Copies the regions into the staging buffer one after the other and returns where each of them
+// goes in the image.
+fn stage_regions<T>(
     logical_device: &LogicalDevice,
     buffer_memory: vk::DeviceMemory,
-    width: u32,
-    height: u32,
-    depth: u32,
-    dimensions: u32,
-    data: &Vec<T>,
-) {
-    let size = (width * height * depth * dimensions) as vk::DeviceSize;
+    size: vk::DeviceSize,
+    regions: &[Region<T>],
+) -> Vec<vk::BufferImageCopy> {
+    let len = regions.iter().map(|region| region.texels()).sum::<usize>();
+    assert!(
+        (len * std::mem::size_of::<T>()) as vk::DeviceSize <= size,
+        "Texture regions do not fit in staging buffer"
+    );
+
     let raw_data = unsafe {
         logical_device
             .value
@@ -293,10 +277,72 @@ fn load_data<T>(
     }
     .expect("Failed to map memory") as *mut T;
 
+    let mut copies = Vec::with_capacity(regions.len());
+    let mut offset = 0;
+    for region in regions {
+        let (width, height, depth) = region.extent;
+        let mut row_offset = offset;
+        for z in 0..depth {
+            for y in 0..height {
+                let start = ((z * region.image_height + y) * region.row_length) as usize;
+                let row = &region.data[start..start + width as usize];
+                unsafe {
+                    raw_data
+                        .add(row_offset)
+                        .copy_from_nonoverlapping(row.as_ptr(), row.len());
+                }
+                row_offset += row.len();
+            }
+        }
+
+        copies.push(vk::BufferImageCopy {
+            buffer_offset: (offset * std::mem::size_of::<T>()) as vk::DeviceSize,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D {
+                x: region.offset.0 as i32,
+                y: region.offset.1 as i32,
+                z: region.offset.2 as i32,
+            },
+            image_extent: vk::Extent3D {
+                width: region.extent.0,
+                height: region.extent.1,
+                depth: region.extent.2,
+            },
+        });
+
+        offset += region.texels();
+    }
+
     unsafe {
-        raw_data.copy_from_nonoverlapping(data.as_ptr(), data.len());
         logical_device.value.unmap_memory(buffer_memory);
     }
+
+    copies
+}
+
+fn copy_regions(
+    logical_device: &LogicalDevice,
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    image: vk::Image,
+    copies: &[vk::BufferImageCopy],
+) {
+    unsafe {
+        logical_device.value.cmd_copy_buffer_to_image(
+            command_buffer,
+            buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            copies,
+        );
+    }
 }
 
 impl StaticTexture {
@@ -310,7 +356,7 @@ impl StaticTexture {
         depth: u32,
         dimensions: u32,
         format: vk::Format,
-        data: &Vec<T>,
+        data: &[T],
     ) -> Self {
         let (value, memory, image_view, buffer, buffer_memory) = new_3d(
             memory_properties,
@@ -324,7 +370,15 @@ impl StaticTexture {
             format,
         );
 
-        load_data(logical_device, buffer_memory, width, height, depth, dimensions, data);
+        let size = (width * height * depth * dimensions) as vk::DeviceSize;
+        let region = Region {
+            offset: (0, 0, 0),
+            extent: (width, height, depth),
+            row_length: width,
+            image_height: height,
+            data,
+        };
+        let copies = stage_regions(logical_device, buffer_memory, size, &[region]);
 
         let command_buffer = command_pool.begin_single_time_commands(logical_device);
         transition_image_layout(
@@ -334,15 +388,7 @@ impl StaticTexture {
             vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         );
-        copy_buffer_to_image(
-            logical_device,
-            command_buffer,
-            buffer,
-            value,
-            width,
-            height,
-            depth,
-        );
+        copy_regions(logical_device, command_buffer, buffer, value, &copies);
         transition_image_layout(
             logical_device,
             command_buffer,
@@ -423,11 +469,23 @@ impl DynamicTexture {
             height,
             depth,
             dimensions,
+            format,
         }
     }
 
-    pub fn update<T>(&self, logical_device: &LogicalDevice, command_pool: &CommandPool, queues: &Queues, data: &Vec<T>) {
-        load_data(logical_device, self.buffer_memory, self.width, self.height, self.depth, self.dimensions, data);
+    pub fn update_regions<T>(
+        &self,
+        logical_device: &LogicalDevice,
+        command_pool: &CommandPool,
+        queues: &Queues,
+        regions: &[Region<T>],
+    ) {
+        if regions.is_empty() {
+            return;
+        }
+
+        let size = (self.width * self.height * self.depth * self.dimensions) as vk::DeviceSize;
+        let copies = stage_regions(logical_device, self.buffer_memory, size, regions);
 
         let command_buffer = command_pool.begin_single_time_commands(logical_device);
         transition_image_layout(
@@ -437,15 +495,7 @@ impl DynamicTexture {
             vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
             vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         );
-        copy_buffer_to_image(
-            logical_device,
-            command_buffer,
-            self.buffer,
-            self.value,
-            self.width,
-            self.height,
-            self.depth,
-        );
+        copy_regions(logical_device, command_buffer, self.buffer, self.value, &copies);
         transition_image_layout(
             logical_device,
             command_buffer,
@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::math::IVec3;
+use crate::volume::{Volume, CHUNK_SIZE};
+use crate::voxel::Voxel;
+
+// Water is mostly transparent and a little reflective, so the terrain below it stays visible.
+pub const WATER: Voxel = Voxel::new(40, 110, 200, 6, 3);
+
+// The amount of water a single voxel holds when it is full.
+pub const MAX_LEVEL: u8 = 8;
+
+const SIDES: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+// A cellular automaton where every water voxel holds a level between 1 and MAX_LEVEL. Water first
+// falls into the voxel below it, then runs over edges and finally levels out with its horizontal
+// neighbours. Only voxels that changed and their neighbours are simulated in the next step, so
+// water that has settled costs nothing.
+pub struct Water {
+    levels: HashMap<IVec3, u8>,
+    active: HashSet<IVec3>,
+    steps: usize,
+}
+
+impl Water {
+    // Every water voxel already in the volume starts out full.
+    pub fn new(volume: &Volume) -> Self {
+        let mut levels = HashMap::new();
+        for key in volume.chunks().map(|(key, _)| *key) {
+            let min = key * CHUNK_SIZE as i32;
+            for (pos, voxel) in volume.iter_box(min, min.add_scalar(CHUNK_SIZE as i32 - 1)) {
+                if voxel == WATER {
+                    levels.insert(pos, MAX_LEVEL);
+                }
+            }
+        }
+
+        Self {
+            active: levels.keys().copied().collect(),
+            levels,
+            steps: 0,
+        }
+    }
+
+    pub fn add(&mut self, volume: &mut Volume, pos: IVec3, level: u8) {
+        if self.level(volume, pos).is_some() {
+            let level = (self.levels.get(&pos).copied().unwrap_or(0) + level).min(MAX_LEVEL);
+            self.levels.insert(pos, level);
+            self.active.insert(pos);
+            volume.set(pos, WATER);
+        }
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    // Advances the simulation by one tick and writes the voxels that changed back into the volume,
+    // which marks them as dirty.
+    pub fn step(&mut self, volume: &mut Volume) {
+        let mut active: Vec<_> = self.active.drain().collect();
+        // Lower voxels go first, so a falling column moves down as a whole
+        active.sort_by_key(|pos| (pos.y, pos.z, pos.x));
+
+        let mut changed = HashSet::new();
+        for pos in active {
+            let start = match self.levels.get(&pos) {
+                Some(&level) => level,
+                None => continue,
+            };
+
+            // Something solid was placed where the water was
+            if self.level(volume, pos).is_none() {
+                self.levels.remove(&pos);
+                changed.insert(pos);
+                continue;
+            }
+
+            let mut level = start;
+            let below = pos - IVec3::y();
+            if let Some(other) = self.level(volume, below) {
+                let amount = level.min(MAX_LEVEL - other);
+                if amount > 0 {
+                    self.levels.insert(below, other + amount);
+                    changed.insert(below);
+                    level -= amount;
+                }
+            }
+
+            // Start at a different side every step, so water does not drift in one direction
+            for i in 0..SIDES.len() {
+                let (x, z) = SIDES[(i + self.steps) % SIDES.len()];
+                let side = pos + IVec3::new(x, 0, z);
+                let other = match self.level(volume, side) {
+                    Some(other) => other,
+                    None => continue,
+                };
+
+                // Water runs over an edge even when it is shallow, otherwise it only flows towards
+                // lower water
+                let downhill = other == 0
+                    && self
+                        .level(volume, side - IVec3::y())
+                        .is_some_and(|below| below < MAX_LEVEL);
+                if level > 0 && (downhill || other + 1 < level) {
+                    self.levels.insert(side, other + 1);
+                    changed.insert(side);
+                    level -= 1;
+                }
+            }
+
+            if level != start {
+                if level == 0 {
+                    self.levels.remove(&pos);
+                } else {
+                    self.levels.insert(pos, level);
+                }
+                changed.insert(pos);
+            }
+        }
+
+        for &pos in &changed {
+            if self.levels.contains_key(&pos) {
+                volume.set(pos, WATER);
+            } else if volume.get(pos) == WATER {
+                volume.set(pos, Voxel::EMPTY);
+            }
+        }
+
+        for pos in changed {
+            self.activate(pos);
+            self.activate(pos + IVec3::y());
+            for &(x, z) in &SIDES {
+                self.activate(pos + IVec3::new(x, 0, z));
+            }
+        }
+
+        self.steps += 1;
+    }
+
+    // The level of water at a position, or None if water can not flow there.
+    fn level(&self, volume: &Volume, pos: IVec3) -> Option<u8> {
+        // Water that flowed in during this step has not been written to the volume yet
+        let voxel = volume.get(pos);
+        match self.levels.get(&pos) {
+            Some(&level) if voxel == WATER || voxel.is_empty() => Some(level),
+            None if volume.contains(pos) && voxel.is_empty() => Some(0),
+            _ => None,
+        }
+    }
+
+    fn activate(&mut self, pos: IVec3) {
+        if self.levels.contains_key(&pos) {
+            self.active.insert(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math;
+
+    // A closed stone basin with a block of water dropped into one corner
+    fn basin() -> (Volume, Water) {
+        let mut volume = Volume::new(IVec3::new(16, 16, 16));
+        let stone = Voxel::solid(128, 128, 128);
+        for pos in math::box_positions(IVec3::zeros(), IVec3::new(15, 0, 15)) {
+            volume.set(pos, stone);
+        }
+        for pos in math::box_positions(IVec3::zeros(), IVec3::new(15, 6, 15)) {
+            if pos.x == 0 || pos.z == 0 || pos.x == 15 || pos.z == 15 {
+                volume.set(pos, stone);
+            }
+        }
+
+        let mut water = Water::new(&volume);
+        for pos in math::box_positions(IVec3::new(2, 8, 2), IVec3::new(4, 10, 4)) {
+            water.add(&mut volume, pos, MAX_LEVEL);
+        }
+        (volume, water)
+    }
+
+    fn total(water: &Water) -> u32 {
+        water.levels.values().map(|&level| level as u32).sum()
+    }
+
+    #[test]
+    fn conserves_water() {
+        let (mut volume, mut water) = basin();
+        let start = total(&water);
+        assert_eq!(start, 27 * MAX_LEVEL as u32);
+
+        for _ in 0..40 {
+            water.step(&mut volume);
+            assert_eq!(total(&water), start);
+        }
+        for pos in water.levels.keys() {
+            assert_eq!(volume.get(*pos), WATER);
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let (mut a_volume, mut a) = basin();
+        let (mut b_volume, mut b) = basin();
+        for _ in 0..10 {
+            a.step(&mut a_volume);
+            b.step(&mut b_volume);
+            assert_eq!(a.levels, b.levels);
+        }
+    }
+}